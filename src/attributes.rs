@@ -0,0 +1,59 @@
+use chrono::NaiveDate;
+use regex::Regex;
+
+/// The priority of a todo from its text, 1 being the most urgent. Understands
+/// `(A)`-style markers at the start, `p1`-style words and `!!!` (1), `!!` (2) or `!` (3).
+pub fn priority(text: &str) -> Option<u8> {
+    let text = text.trim();
+    let letter = Regex::new(r"^\(([A-Z])\)(\s|$)").unwrap();
+    if let Some(caps) = letter.captures(text) {
+        return Some(caps[1].as_bytes()[0] - b'A' + 1);
+    }
+    text.split_whitespace().find_map(|word| match word {
+        "!!!" => Some(1),
+        "!!" => Some(2),
+        "!" => Some(3),
+        _ => word
+            .strip_prefix('p')
+            .and_then(|number| number.parse().ok())
+            .filter(|number| (1..=9).contains(number)),
+    })
+}
+
+/// Parses a priority given on the command line, in any of the forms [`priority`]
+/// understands or as a bare letter or number, e.g. `A`, `p1`, `1` or `!!!`
+pub fn parse_priority(value: &str) -> Result<u8, String> {
+    let value = value.trim();
+    let bare = match value.as_bytes() {
+        [letter @ b'A'..=b'Z'] | [b'(', letter @ b'A'..=b'Z', b')'] => Some(letter - b'A' + 1),
+        _ => value
+            .parse()
+            .ok()
+            .filter(|number| (1..=26).contains(number)),
+    };
+    bare.or_else(|| priority(value))
+        .ok_or_else(|| format!("`{value}` isn't a priority, use A-Z, p1-p9 or !!!, !! and !"))
+}
+
+/// The date of the `due:YYYY-MM-DD` attribute of a todo
+pub fn due(text: &str) -> Option<NaiveDate> {
+    text.split_whitespace()
+        .find_map(|word| word.strip_prefix("due:"))
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+}
+
+/// The `id:` attribute of a todo, see `todo ids`
+pub fn id(text: &str) -> Option<&str> {
+    text.split_whitespace()
+        .find_map(|word| word.strip_prefix("id:"))
+}
+
+/// The IDs of the todos a todo waits for, from its `blocked-by:` attributes, e.g.
+/// `blocked-by:k3f9` or `blocked-by:k3f9,x1y2`
+pub fn blockers(text: &str) -> Vec<&str> {
+    text.split_whitespace()
+        .filter_map(|word| word.strip_prefix("blocked-by:"))
+        .flat_map(|ids| ids.split(','))
+        .filter(|id| !id.is_empty())
+        .collect()
+}
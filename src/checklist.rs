@@ -0,0 +1,157 @@
+use crate::{
+    state::{states, State},
+    todo_file::TodoFile,
+    utils::{ensure_can_prompt, get_checklists_path, get_todo_file_path},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use chrono::{Days, Local, NaiveDate};
+use inquire::prompt_confirmation;
+use regex::{Captures, Regex};
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+/// Saves the todos of the current file, or of one of its sections, as a checklist.
+/// The todos are unchecked, their IDs dropped and, with `from`, their due dates turned
+/// into offsets from it.
+pub fn save(
+    name: &str,
+    section: Option<String>,
+    from: Option<NaiveDate>,
+    yes: bool,
+) -> AnyResult<()> {
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let range = file.scope(section.as_deref())?;
+
+    let id = Regex::new(r"\s+id:\w+").unwrap();
+    let due = Regex::new(r"(^|\s)due:(\d{4}-\d{2}-\d{2})\b").unwrap();
+    let lines: Vec<String> = file
+        .lines()
+        .skip(range.start)
+        .take(range.len())
+        .map(|line| {
+            let line = states().set(line, State::Todo);
+            let line = id.replace_all(&line, "");
+            match from {
+                Some(from) => due
+                    .replace_all(&line, |caps: &Captures| {
+                        match NaiveDate::parse_from_str(&caps[2], "%Y-%m-%d") {
+                            Ok(date) => {
+                                format!("{}due:{:+}d", &caps[1], (date - from).num_days())
+                            }
+                            Err(_) => caps[0].to_string(),
+                        }
+                    })
+                    .to_string(),
+                None => line.to_string(),
+            }
+        })
+        .collect();
+    let content = lines.join("\n").trim_matches('\n').to_string();
+    if content.is_empty() {
+        return Err(anyhow!("There's nothing to save"));
+    }
+
+    let path = checklist_path(name)?;
+    if path.exists() && !yes {
+        ensure_can_prompt("Pass --yes to replace it.")?;
+        if !prompt_confirmation(format!("Replace the checklist `{name}`?"))? {
+            return Ok(());
+        }
+    }
+    fs::create_dir_all(path.parent().unwrap()).context("Failed to create checklist directory")?;
+    fs::write(&path, content + "\n").context("Failed to save checklist")?;
+    println!("✅ Saved checklist `{name}`");
+    Ok(())
+}
+
+pub fn list() -> AnyResult<()> {
+    let dir = get_checklists_path()?;
+    let mut names: Vec<String> = match fs::read_dir(&dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension()? != "md" {
+                    return None;
+                }
+                Some(path.file_stem()?.to_string_lossy().to_string())
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    if names.is_empty() {
+        println!("No checklists saved yet. Save one with `todo checklist save <name>`");
+        return Ok(());
+    }
+    names.sort();
+    for name in names {
+        println!("{name}");
+    }
+    Ok(())
+}
+
+/// Inserts a fresh copy of a checklist at the end of the section titled `under`, or of
+/// the file. Due dates saved as offsets, like `due:+3d` or `due:-1w`, are counted from
+/// `from`, today by default.
+pub fn apply(name: &str, under: Option<String>, from: Option<NaiveDate>) -> AnyResult<()> {
+    let path = checklist_path(name)?;
+    let checklist = fs::read_to_string(&path)
+        .map_err(|_| anyhow!("No checklist named `{name}`, see `todo checklist list`"))?;
+    let from = from.unwrap_or_else(|| Local::now().date_naive());
+    let offset = Regex::new(r"(^|\s)due:([+-])(\d+)([dw])\b").unwrap();
+    let lines: Vec<String> = checklist
+        .lines()
+        .map(|line| {
+            offset
+                .replace_all(line, |caps: &Captures| {
+                    let days = caps[3].parse::<u64>().unwrap_or_default()
+                        * if &caps[4] == "w" { 7 } else { 1 };
+                    let date = if &caps[2] == "+" {
+                        from.checked_add_days(Days::new(days))
+                    } else {
+                        from.checked_sub_days(Days::new(days))
+                    };
+                    match date {
+                        Some(date) => format!("{}due:{}", &caps[1], date.format("%Y-%m-%d")),
+                        None => caps[0].to_string(),
+                    }
+                })
+                .to_string()
+        })
+        .collect();
+
+    let todo_path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&todo_path)?;
+    match &under {
+        Some(title) => {
+            if file.insert_into_section(title, lines.clone()) {
+                println!("ℹ No section titled `{title}`, added it");
+            }
+        }
+        None => {
+            for line in &lines {
+                file.push_line(line.clone());
+            }
+        }
+    }
+    file.write(&todo_path)?;
+    println!("✅ Added {} line(s) from checklist `{name}`", lines.len());
+    Ok(())
+}
+
+/// The file of the checklist `name`, which must be a plain file name so the checklist
+/// stays in the checklists directory
+fn checklist_path(name: &str) -> AnyResult<PathBuf> {
+    let mut components = Path::new(name).components();
+    let plain = matches!(components.next(), Some(Component::Normal(_)))
+        && components.next().is_none()
+        && !name.contains(['/', '\\']);
+    if !plain {
+        return Err(anyhow!(
+            "Invalid checklist name `{name}`, it can't contain path separators or `..`"
+        ));
+    }
+    Ok(get_checklists_path()?.join(format!("{name}.md")))
+}
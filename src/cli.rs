@@ -1,4 +1,8 @@
-use clap::{ArgGroup, Parser, Subcommand};
+use crate::attributes::parse_priority;
+use chrono::NaiveDate;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
 #[command(
@@ -11,13 +15,29 @@ use clap::{ArgGroup, Parser, Subcommand};
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Commands>,
+
+    /// Never prompt, fail instead when input is needed. Implied when not run in a terminal
+    #[arg(long, global = true)]
+    pub no_input: bool,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
-    /// Create a hard link to this directory's todo file in the current directory
+    /// Create the todo file for the current directory
+    #[command()]
+    Init(InitArgs),
+
+    /// Create the todo file for the current directory from a template and open it
+    New {
+        /// The template to use, from .todo/templates in this directory or its parents,
+        /// or from ~/.todo/templates. Defaults to the one called `default`
+        #[arg(short, long)]
+        template: Option<String>,
+    },
+
+    /// Link this directory's todo file into the current directory
     #[command(alias = "s")]
-    Sync,
+    Sync(SyncArgs),
 
     /// Undo the sync command
     #[command()]
@@ -35,46 +55,201 @@ pub enum Commands {
     #[command(alias = "d")]
     Delete(DeleteArgs),
 
+    /// Restore or permanently remove deleted todos
+    #[command()]
+    Trash(TrashArgs),
+
+    /// Save reusable checklists and add them to the todo file
+    Checklist(ChecklistArgs),
+
+    /// Move the todos of a directory after moving or renaming it
+    #[command()]
+    Mv { from: PathBuf, to: PathBuf },
+
+    /// Find orphaned, empty and misnamed todo files and broken syncs
+    #[command()]
+    Doctor {
+        /// Fix the problems found, asking what to do with orphaned todos
+        #[arg(long)]
+        fix: bool,
+    },
+
     /// Update todo cli
     #[command()]
     Update,
 
     /// Fuzzily find todos
     #[command(alias = "find")]
-    Search { query: Option<String> },
+    Search {
+        query: Option<String>,
+        /// Only search the todos under this heading
+        #[arg(short, long)]
+        section: Option<String>,
+    },
+
+    /// Add a todo
+    #[command(alias = "a")]
+    Add {
+        #[arg(required = true)]
+        text: Vec<String>,
+        /// Add the todo at the end of the section with this heading
+        #[arg(short, long)]
+        section: Option<String>,
+    },
+
+    /// Move a todo, with its subtasks, up or down or to another section
+    Move {
+        /// Text to match, a todo number or an ID, like for `todo check`
+        query: String,
+        /// Where to move it among the todos at the same level
+        #[arg(value_enum, required_unless_present = "to_section")]
+        position: Option<Position>,
+        /// The heading of the section to move it to, added when missing
+        #[arg(long, conflicts_with = "position")]
+        to_section: Option<String>,
+    },
+
+    /// Show the todo to work on next: the most urgent unchecked one, due the earliest
+    Next {
+        /// Look at the todos of every directory instead of the current one
+        #[arg(short, long)]
+        global: bool,
+    },
+
+    /// Show how todos block each other through their `blocked-by:` attributes
+    Graph {
+        /// Print the graph in the DOT format of Graphviz, e.g. `todo graph --dot | dot -Tsvg`
+        #[arg(long)]
+        dot: bool,
+    },
+
+    /// Sort the todos of each section, keeping subtasks with their parents
+    Sort {
+        #[arg(long, value_enum, default_value_t = SortKey::State)]
+        by: SortKey,
+        /// Only sort the todos under this heading
+        #[arg(short, long)]
+        section: Option<String>,
+    },
 
     /// Fuzzily find todos, check them
     #[command(alias = "done")]
     Check {
-        query: Option<String>,
+        #[command(flatten)]
+        select: SelectArgs,
         /// Check all todos
         #[arg(short, long)]
         all: bool,
+        /// Check todos even when they are blocked by unchecked ones
+        #[arg(short, long)]
+        force: bool,
     },
 
     /// Fuzzily find todos, uncheck them
     #[command(alias = "undo")]
     Uncheck {
-        query: Option<String>,
+        #[command(flatten)]
+        select: SelectArgs,
         /// Uncheck all todos
         #[arg(short, long)]
         all: bool,
     },
 
+    /// Fuzzily find todos, mark them as in progress (`[/]`)
+    Start {
+        #[command(flatten)]
+        select: SelectArgs,
+        /// Start all todos waiting to be started
+        #[arg(short, long)]
+        all: bool,
+    },
+
+    /// Fuzzily find todos, mark them as cancelled (`[-]`)
+    Cancel {
+        #[command(flatten)]
+        select: SelectArgs,
+        /// Cancel all open todos
+        #[arg(short, long)]
+        all: bool,
+    },
+
+    /// Fuzzily find todos, mark them as deferred (`[>]`)
+    Defer {
+        #[command(flatten)]
+        select: SelectArgs,
+        /// Defer all todos waiting or in progress
+        #[arg(short, long)]
+        all: bool,
+    },
+
     /// Fuzzily find todos, remove them
     #[command(alias = "rm")]
     Remove {
-        query: Option<String>,
+        #[command(flatten)]
+        select: SelectArgs,
         /// Remove all todos without deleting the file
         #[arg(short, long)]
         all: bool,
     },
 
+    /// Change the text of a todo
+    Edit {
+        /// Text to match, a todo number or an ID, like for `todo check`
+        query: Option<String>,
+        /// The new text. Prompted for, starting from the current text, when not given
+        text: Vec<String>,
+    },
+
+    /// Open the todo file in the editor at the line of a todo
+    Open {
+        /// Text to match, a todo number or an ID, like for `todo check`
+        query: Option<String>,
+    },
+
+    /// Give every todo a short ID to refer to it with `id:<id>`
+    Ids,
+
     /// Count the number of todos
     #[command()]
     Count(CountArgs),
 }
 
+/// How the todos to act on are picked
+#[derive(Debug, Default, Args)]
+pub struct SelectArgs {
+    /// Text to match, todo numbers as shown by `todo list` like `3` or `2-4,7`,
+    /// or an ID like `id:k3f9`
+    pub query: Option<String>,
+
+    /// Act on every todo matching the query, not only the best matches
+    #[arg(long)]
+    pub all_matches: bool,
+
+    /// Act on every todo whose fuzzy match score is at least this
+    #[arg(long, conflicts_with_all = ["exact", "regex"])]
+    pub min_score: Option<i64>,
+
+    /// Match todos containing the query, ignoring case
+    #[arg(long, conflicts_with = "regex")]
+    pub exact: bool,
+
+    /// Match todos against the query as a regular expression
+    #[arg(long)]
+    pub regex: bool,
+
+    /// Don't ask for confirmation before acting on several matches
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Show the todos that would be changed without changing anything
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Only consider the todos under this heading
+    #[arg(short, long)]
+    pub section: Option<String>,
+}
+
 #[derive(Debug, Parser)]
 #[command(group(
     ArgGroup::new("mode")
@@ -89,6 +264,147 @@ pub struct DeleteArgs {
     /// Remove in the current directory recursively
     #[arg(short, long, group = "mode")]
     pub recurse: bool,
+
+    /// Show the todo files that would be deleted without deleting them
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Don't ask for confirmation
+    #[arg(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct InitArgs {
+    /// Keep the todo file inside this directory instead of in ~/.todo/todos
+    #[arg(short, long)]
+    pub local: bool,
+
+    /// Where to put the local todo file, relative to this directory
+    #[arg(short, long, requires = "local")]
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct SyncArgs {
+    #[command(subcommand)]
+    pub action: Option<SyncSubcommand>,
+
+    /// How to link the todo file
+    #[arg(short, long, value_enum, default_value_t = SyncMode::Hardlink)]
+    pub mode: SyncMode,
+
+    /// Where to put the synced file, defaults to the todo filename in the current directory
+    #[arg(short, long)]
+    pub target: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum SyncSubcommand {
+    /// Check whether the synced file is still linked to the todo file
+    Status,
+
+    /// Recreate a missing or broken link
+    Repair,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum Position {
+    /// Swap with the todo before it
+    Up,
+    /// Swap with the todo after it
+    Down,
+    /// Before the other todos
+    Top,
+    /// After the other todos
+    Bottom,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum SortKey {
+    /// Unchecked todos first
+    State,
+    /// Earliest `due:` date first, todos without one last
+    Due,
+    /// Most urgent first, todos without a priority last
+    Priority,
+    /// Alphabetically, ignoring case
+    Alpha,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// A hard link, only works within the same filesystem
+    Hardlink,
+    /// A symbolic link to the todo file
+    Symlink,
+    /// A plain copy, refreshed by `todo sync repair`
+    Copy,
+    /// A copy that is merged both ways with the todo file on every command
+    Merge,
+}
+
+#[derive(Debug, Parser)]
+pub struct ChecklistArgs {
+    #[command(subcommand)]
+    pub action: ChecklistSubcommand,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum ChecklistSubcommand {
+    /// Save the todos of the todo file, or of one of its sections, as a checklist
+    Save {
+        name: String,
+        /// Only save the todos under this heading
+        #[arg(short, long)]
+        section: Option<String>,
+        /// Save due dates as offsets from this date, e.g. `due:+3d`
+        #[arg(long)]
+        from: Option<NaiveDate>,
+        /// Replace an existing checklist without asking
+        #[arg(short, long)]
+        yes: bool,
+    },
+
+    /// List the saved checklists
+    #[command(alias = "ls")]
+    List,
+
+    /// Add a fresh, unchecked copy of a checklist to the todo file
+    Apply {
+        name: String,
+        /// Add the todos at the end of the section with this heading
+        #[arg(short, long)]
+        under: Option<String>,
+        /// Count due date offsets from this date instead of today
+        #[arg(long)]
+        from: Option<NaiveDate>,
+    },
+}
+
+#[derive(Debug, Parser)]
+pub struct TrashArgs {
+    #[command(subcommand)]
+    pub action: TrashSubcommand,
+}
+
+#[derive(Debug, Subcommand, Clone)]
+pub enum TrashSubcommand {
+    /// List the deleted todos
+    #[command(alias = "ls")]
+    List,
+
+    /// Put deleted todos back where they were
+    Restore { id: String },
+
+    /// Permanently remove deleted todos
+    Empty {
+        /// Only remove todos deleted longer ago than this, e.g. 30d, 2w or 12h
+        #[arg(long)]
+        older_than: Option<String>,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -106,6 +422,9 @@ pub enum ConfigSubcommand {
 
 #[derive(Debug, Parser)]
 pub struct CountArgs {
+    /// Only count the todos under this heading
+    #[arg(short, long, global = true)]
+    pub section: Option<String>,
     #[command(subcommand)]
     pub filter: Option<CountSubcommand>,
 }
@@ -126,6 +445,15 @@ pub struct ListArgs {
     /// Print the entire todo file
     #[arg(short, long)]
     pub raw: bool,
+    /// Only list the todos under this heading
+    #[arg(short, long, global = true)]
+    pub section: Option<String>,
+    /// Only list the todos at least this urgent, e.g. `B`, `p2` or `!!`
+    #[arg(short, long, global = true, value_parser = parse_priority)]
+    pub priority: Option<u8>,
+    /// List the todos in this order instead of by section
+    #[arg(long, global = true, value_enum)]
+    pub sort: Option<SortKey>,
     #[command(subcommand)]
     pub filter: Option<ListSubcommand>,
 }
@@ -1,8 +1,10 @@
-use crate::utils::{get_config_path, get_todo_path, update_todos};
-use anyhow::{anyhow, Result as AnyResult};
+use crate::error::TodoError;
+use crate::state::{default_states, State};
+use crate::utils::{can_prompt, ensure_can_prompt, get_config_path, get_todo_path, update_todos};
+use anyhow::{Context, Result as AnyResult};
 use inquire::{Select, Text};
 use serde::{Deserialize, Serialize};
-use std::{fs, io, process::exit};
+use std::{collections::BTreeMap, fmt, fs, io, path::PathBuf, process::exit};
 
 #[derive(Debug, Deserialize)]
 struct RawConfig {
@@ -10,6 +12,11 @@ struct RawConfig {
     extension: Option<String>,
     editor: Option<String>,
     flags: Option<Vec<String>>,
+    line_args: Option<Vec<String>>,
+    identity: Option<Identity>,
+    roots: Option<BTreeMap<String, PathBuf>>,
+    editors: Option<BTreeMap<String, String>>,
+    states: Option<BTreeMap<String, State>>,
 }
 
 impl Default for RawConfig {
@@ -19,6 +26,11 @@ impl Default for RawConfig {
             extension: Some(".md".into()),
             editor: Some("$EDITOR".into()),
             flags: Some(Vec::new()),
+            line_args: Some(Vec::new()),
+            identity: Some(Identity::Path),
+            roots: Some(BTreeMap::new()),
+            editors: Some(BTreeMap::new()),
+            states: Some(default_states()),
         }
     }
 }
@@ -29,6 +41,48 @@ pub struct Config {
     pub extension: String,
     pub editor: String,
     pub flags: Vec<String>,
+    /// The arguments opening the todo file at a line, with `{file}` and `{line}` replaced,
+    /// e.g. `["--line", "{line}", "{file}"]`. Guessed from the editor's name when empty.
+    pub line_args: Vec<String>,
+    pub identity: Identity,
+    /// Directories outside the home directory whose todos are kept under their own name
+    /// in the store, e.g. `work = "/mnt/work"` keeps `/mnt/work/api` under `work/api`
+    pub roots: BTreeMap<String, PathBuf>,
+    /// Editors used instead of `editor` for todo files with these extensions,
+    /// e.g. `".org" = "emacs +{line} {file}"`
+    pub editors: BTreeMap<String, String>,
+    /// The checkbox markers understood and the state of todos using them,
+    /// e.g. `"/" = "doing"` for `- [/] todo`
+    pub states: BTreeMap<String, State>,
+}
+
+/// How the todo file of a directory is found in the store
+#[derive(Debug, Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Identity {
+    /// By the path of the directory
+    #[default]
+    Path,
+    /// By the `origin` remote of the git repository, so that all clones share one list.
+    /// Directories without a remote fall back to their path.
+    Remote,
+}
+
+impl fmt::Display for Identity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Identity::Path => "path",
+            Identity::Remote => "remote",
+        })
+    }
+}
+
+/// The content of the marker created by `todo init --local`, for projects that keep their
+/// todo file inside the project instead of in the store.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalConfig {
+    /// The todo file, relative to the directory containing the marker
+    pub path: PathBuf,
 }
 
 impl Default for Config {
@@ -41,6 +95,10 @@ impl Config {
     fn names_changed(old: &Self, new: &Self) -> bool {
         old.filename != new.filename || old.extension != new.extension
     }
+
+    fn identity_changed(old: &Self, new: &Self) -> bool {
+        old.identity != new.identity
+    }
 }
 
 impl From<RawConfig> for Config {
@@ -50,6 +108,11 @@ impl From<RawConfig> for Config {
             extension: value.extension.unwrap_or(".md".into()),
             editor: value.editor.unwrap_or("$EDITOR".into()),
             flags: value.flags.unwrap_or_default(),
+            line_args: value.line_args.unwrap_or_default(),
+            identity: value.identity.unwrap_or_default(),
+            roots: value.roots.unwrap_or_default(),
+            editors: value.editors.unwrap_or_default(),
+            states: value.states.unwrap_or_else(default_states),
         }
     }
 }
@@ -58,20 +121,34 @@ pub fn load_config() -> AnyResult<Option<Config>> {
     let config_path = get_config_path()?;
     let config = fs::read_to_string(&config_path);
     match config {
-        Ok(config_str) => Ok(Some(
-            toml::from_str::<RawConfig>(&config_str)
-                .unwrap_or_default()
-                .into(),
-        )),
+        Ok(config_str) => {
+            let config: Config = toml::from_str::<RawConfig>(&config_str)
+                .map_err(|e| TodoError::ConfigInvalid {
+                    path: config_path.clone(),
+                    reason: e.message().to_string(),
+                })?
+                .into();
+            if let Some(marker) = config.states.keys().find(|m| m.chars().count() != 1) {
+                return Err(TodoError::ConfigInvalid {
+                    path: config_path,
+                    reason: format!("state markers are single characters, not `{marker}`"),
+                }
+                .into());
+            }
+            Ok(Some(config))
+        }
         Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
-        Err(err) => Err(anyhow!(
-            "❌ Failed to read config file at {}: {err}",
-            config_path.display()
-        )),
+        Err(err) => Err(err)
+            .with_context(|| format!("Failed to read config file at {}", config_path.display())),
     }
 }
 
 pub fn configure(user_triggered: bool) -> AnyResult<Config> {
+    if !user_triggered && !can_prompt() {
+        eprintln!("ℹ No config yet, using the default config. Run `todo config` to save one.");
+        return Ok(Config::default());
+    }
+    ensure_can_prompt("Edit the config file directly instead.")?;
     let options = vec!["1) Use default config", "2) Customize config", "3) Cancel"];
     let prompt = if user_triggered {
         "Select an option"
@@ -80,12 +157,12 @@ pub fn configure(user_triggered: bool) -> AnyResult<Config> {
     };
     let choice = Select::new(prompt, options)
         .prompt()
-        .map_err(|e| anyhow!("❌ Prompt failed: {e}"))?;
+        .context("Prompt failed")?;
     let config = match choice.chars().next().unwrap() {
         '1' => Config::default(),
         '2' => {
             let old_config = if user_triggered {
-                load_config()?.unwrap_or_default()
+                load_config().unwrap_or_default().unwrap_or_default()
             } else {
                 Default::default()
             };
@@ -93,27 +170,38 @@ pub fn configure(user_triggered: bool) -> AnyResult<Config> {
                 filename: Text::new("Filename:")
                     .with_default(&old_config.filename)
                     .prompt()
-                    .map_err(|e| anyhow!("❌ Failed to get filename: {e}"))?,
+                    .context("Failed to get filename")?,
                 extension: Text::new("Extension:")
                     .with_default(&old_config.extension)
                     .prompt()
-                    .map_err(|e| anyhow!("❌ Failed to get extension: {e}"))?,
+                    .context("Failed to get extension")?,
                 editor: Text::new("Editor:")
                     .with_default(&old_config.editor)
                     .prompt()
-                    .map_err(|e| anyhow!("❌ Failed to get editor: {e}"))?,
+                    .context("Failed to get editor")?,
                 flags: Text::new("Editor flags:")
                     .with_default(&old_config.flags.join(" "))
                     .prompt()
                     .map(|s| s.split_whitespace().map(|s| s.to_string()).collect())
-                    .map_err(|e| anyhow!("Failed to get editor flags: {e}"))?,
+                    .context("Failed to get editor flags")?,
+                line_args: old_config.line_args.clone(),
+                identity: Select::new(
+                    "Identify projects by:",
+                    vec![Identity::Path, Identity::Remote],
+                )
+                .with_starting_cursor(old_config.identity as usize)
+                .prompt()
+                .context("Failed to get project identity")?,
+                roots: old_config.roots.clone(),
+                editors: old_config.editors.clone(),
+                states: old_config.states.clone(),
             }
         }
         '3' => exit(0),
         _ => unreachable!("❌ No such option selected."),
     };
 
-    let old_config = load_config()?;
+    let old_config = load_config().unwrap_or_default();
     if let Some(ref old_config) = old_config {
         if config == *old_config {
             println!("ℹ The config wasn't changed");
@@ -122,19 +210,23 @@ pub fn configure(user_triggered: bool) -> AnyResult<Config> {
     }
     let config_path = get_config_path()?;
     fs::create_dir_all(config_path.parent().unwrap())
-        .map_err(|e| anyhow!("❌ Failed to create config directory: {e}"))?;
-    fs::write(&config_path, toml::to_string(&config)?)
-        .map_err(|e| anyhow!("❌ Failed to write config file: {e}"))?;
+        .context("Failed to create config directory")?;
+    fs::write(&config_path, toml::to_string(&config)?).context("Failed to write config file")?;
     println!("✅ Saved config to {}", config_path.display());
+    if old_config
+        .as_ref()
+        .is_some_and(|old_config| Config::identity_changed(&config, old_config))
+    {
+        println!(
+            "ℹ Projects are now identified by {}, existing todo files were left in place",
+            config.identity
+        );
+    }
     if old_config.is_some() && Config::names_changed(&config, &old_config.unwrap()) {
         let todos_path = get_todo_path()?;
         if todos_path.exists() {
-            match update_todos(todos_path, &config) {
-                Ok(_) => println!("✅ Updated todos"),
-                Err(err) => {
-                    return Err(anyhow!("❌ Failed to update todos: {err:?}"));
-                }
-            }
+            update_todos(todos_path, &config).context("Failed to update todos")?;
+            println!("✅ Updated todos");
         }
     }
     Ok(config)
@@ -1,7 +1,10 @@
-use crate::{cli::CountSubcommand, utils::get_todo_file_path};
+use crate::{
+    cli::CountSubcommand,
+    state::{states, State},
+    todo_file::TodoFile,
+    utils::get_todo_file_path,
+};
 use anyhow::Result as AnyResult;
-use regex::Regex;
-use std::fs;
 
 #[derive(Debug)]
 pub enum CountFilter {
@@ -20,23 +23,19 @@ impl From<Option<CountSubcommand>> for CountFilter {
     }
 }
 
-pub fn count(filter: CountFilter) -> AnyResult<()> {
-    let regex = match filter {
-        CountFilter::All => r"^\s*[-*+]? ?\[( |x)\](.+)$",
-        CountFilter::Checked => r"^\s*[-*+]? ?\[(x)\](.+)$",
-        CountFilter::Unchecked => r"^\s*[-*+]? ?\[( )\](.+)$",
-    };
-    let regex = Regex::new(regex).unwrap();
-    let content = fs::read_to_string(get_todo_file_path()?)?;
+pub fn count(filter: CountFilter, section: Option<String>) -> AnyResult<()> {
+    let regex = states().regex_for(|state| match filter {
+        CountFilter::All => true,
+        CountFilter::Checked => !state.is_open(),
+        CountFilter::Unchecked => state.is_open(),
+    });
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let scope = file.scope(section.as_deref())?;
 
-    let todos: Vec<(&str, bool)> = content
-        .lines()
-        .filter_map(|line| {
-            regex.captures(line).map(|caps| {
-                let checked = caps.get(1).unwrap().as_str() == "x";
-                (line.trim_end(), checked)
-            })
-        })
+    let todos: Vec<State> = file
+        .lines_outside_code()
+        .filter(|(i, _)| scope.contains(i))
+        .filter_map(|(_, line)| states().state(&regex.captures(line)?[1]))
         .collect();
 
     if todos.is_empty() {
@@ -48,19 +47,36 @@ pub fn count(filter: CountFilter) -> AnyResult<()> {
         return Ok(());
     }
 
+    // States without todos are left out of the breakdown
+    let per_state = |open: bool| {
+        for state in State::ALL
+            .into_iter()
+            .filter(|state| state.is_open() == open)
+        {
+            let count = todos.iter().filter(|other| **other == state).count();
+            if count > 0 {
+                println!("    {}: {count}", capitalize(&state.to_string()));
+            }
+        }
+    };
     match filter {
         CountFilter::All => {
             let all = todos.len();
-            let checked = todos
-                .iter()
-                .filter(|(_, checked)| *checked)
-                .collect::<Vec<_>>()
-                .len();
+            let unchecked = todos.iter().filter(|state| state.is_open()).count();
             println!("Total: {all}");
-            println!("  Checked: {checked}");
-            println!("  Unchecked: {}", all - checked);
+            println!("  Checked: {}", all - unchecked);
+            per_state(false);
+            println!("  Unchecked: {unchecked}");
+            per_state(true);
         }
         _ => println!("{}", todos.len()),
     }
     Ok(())
 }
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or(String::new(), |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
@@ -1,34 +1,87 @@
 use crate::{
     cli::DeleteArgs,
-    utils::{get_cwd_todo_dir, get_todo_file_path, get_todo_path},
+    todo_file::TodoFile,
+    trash::trash,
+    utils::{
+        collect_files, ensure_can_prompt, get_cwd_todo_dir, get_original_dir, get_todo_file_path,
+        get_todo_path,
+    },
 };
-use anyhow::{anyhow, Result as AnyResult};
+use anyhow::{Context, Result as AnyResult};
 use inquire::prompt_confirmation;
-use std::fs;
+use std::path::Path;
 
 pub fn delete(args: DeleteArgs) -> AnyResult<()> {
     let todo_path = get_todo_path()?;
     let cwd_todo_dir = get_cwd_todo_dir()?;
     let file_path = get_todo_file_path()?;
+    let confirm = |message: &str| -> AnyResult<bool> {
+        if args.yes {
+            return Ok(true);
+        }
+        ensure_can_prompt("Pass --yes to delete without confirmation.")?;
+        Ok(prompt_confirmation(message)?)
+    };
+    if args.dry_run {
+        let target = if args.all {
+            &todo_path
+        } else if args.recurse {
+            &cwd_todo_dir
+        } else {
+            &file_path
+        };
+        return preview(target);
+    }
     if args.all {
-        if prompt_confirmation(
-            "Are you sure you want to delete ALL your todos? This cannot be undone.",
+        if confirm(
+            "Are you sure you want to delete ALL your todos? They can be restored from the trash.",
         )? {
-            fs::remove_dir_all(&todo_path)
-                .map_err(|e| anyhow!("❌ Failed to remove all todos: {e}"))?;
-            println!("✅ All todos deleted.");
+            let id = trash(&todo_path).context("Failed to remove all todos")?;
+            println!("✅ All todos deleted. Restore them with `todo trash restore {id}`");
         }
     } else if args.recurse {
-        if prompt_confirmation(
-            "Are you sure you want to delete todo files in this directory and all subdirectories? This cannot be undone.",
+        if confirm(
+            "Are you sure you want to delete todo files in this directory and all subdirectories? They can be restored from the trash.",
         )? {
-            fs::remove_dir_all(&cwd_todo_dir)
-                .map_err(|e| anyhow!("❌ Failed to delete todos recursively: {e}"))?;
-            println!("✅ Todos deleted recursively in this directory and subdirectories.");
+            let id = trash(&cwd_todo_dir)
+                .context("Failed to delete todos recursively")?;
+            println!("✅ Todos deleted recursively in this directory and subdirectories. Restore them with `todo trash restore {id}`");
         }
-    } else if prompt_confirmation("Are you sure you want to delete the todo for this folder?")? {
-        fs::remove_file(&file_path).map_err(|e| anyhow!("❌ Failed to delete todo file: {e}"))?;
-        println!("✅ Todo deleted for this folder.");
+    } else if confirm("Are you sure you want to delete the todo for this folder?")? {
+        let id = trash(&file_path).context("Failed to delete todo file")?;
+        println!("✅ Todo deleted for this folder. Restore it with `todo trash restore {id}`");
+    }
+    Ok(())
+}
+
+/// Lists the todo files under `target` with the directory they belong to and how many
+/// todos they have.
+fn preview(target: &Path) -> AnyResult<()> {
+    let mut files = Vec::new();
+    if target.is_dir() {
+        collect_files(target, &mut files)?;
+    } else if target.exists() {
+        files.push(target.to_path_buf());
+    }
+    if files.is_empty() {
+        println!("No todo files to delete.");
+        return Ok(());
+    }
+    let mut total = 0;
+    println!("Would delete {} todo file(s):", files.len());
+    for file in &files {
+        let todos = TodoFile::read(file)
+            .map(|content| content.todo_lines().len())
+            .unwrap_or_default();
+        total += todos;
+        let dir = file.parent().unwrap();
+        // Local todo files aren't in the store
+        let project = get_original_dir(dir)
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| dir.to_path_buf());
+        println!("  {} ({todos} todo(s))", project.display());
     }
+    println!("{total} todo(s) in total");
     Ok(())
 }
@@ -0,0 +1,263 @@
+use crate::{
+    config::load_config,
+    relocate::{merge_into, move_tree, relocate},
+    sync::{
+        load_sync_records, repair_record, save_sync_records, sync_status, SyncRecord, SyncStatus,
+    },
+    trash::trash,
+    utils::{
+        can_prompt, collect_files, get_legacy_todo_dirs, get_original_dir,
+        get_store_todo_file_path, get_todo_path,
+    },
+};
+use anyhow::{Context, Result as AnyResult};
+use inquire::{Select, Text};
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug)]
+enum Problem {
+    /// The directory the todo file belongs to doesn't exist anymore
+    Orphaned { file: PathBuf, dir: PathBuf },
+    /// The todo file has no content
+    Empty { file: PathBuf },
+    /// The todo file isn't named like the config says, e.g. after changing it by hand
+    Misnamed { file: PathBuf, expected: PathBuf },
+    /// The synced file is no longer linked to its todo file
+    BrokenSync {
+        record: SyncRecord,
+        status: SyncStatus,
+    },
+    /// An older version kept the todos of the current directory in the directory itself
+    Legacy { file: PathBuf, expected: PathBuf },
+    /// The sync records can't be read, so no sync can be checked
+    UnreadableSyncs { reason: String },
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Orphaned { file, dir } => write!(
+                f,
+                "Orphaned: {} ({} no longer exists)",
+                file.display(),
+                dir.display()
+            ),
+            Problem::Empty { file } => write!(f, "Empty: {}", file.display()),
+            Problem::Misnamed { file, expected } => write!(
+                f,
+                "Misnamed: {} (expected {})",
+                file.display(),
+                expected.file_name().unwrap_or_default().to_string_lossy()
+            ),
+            Problem::BrokenSync { record, status } => write!(
+                f,
+                "Broken sync: {} ({}) is {status}",
+                record.target.display(),
+                record.mode
+            ),
+            Problem::Legacy { file, expected } => write!(
+                f,
+                "Old location: {} may hold the todos of this directory (expected at {})",
+                file.display(),
+                expected.display()
+            ),
+            Problem::UnreadableSyncs { reason } => write!(f, "Unreadable syncs: {reason}"),
+        }
+    }
+}
+
+pub fn doctor(fix: bool) -> AnyResult<()> {
+    let problems = diagnose()?;
+    if problems.is_empty() {
+        println!("✅ No problems found");
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("⚠️ {problem}");
+    }
+    if !fix {
+        println!("Run `todo doctor --fix` to fix them");
+        return Ok(());
+    }
+    println!();
+    let mut fixed = 0;
+    for problem in &problems {
+        if fix_problem(problem)? {
+            fixed += 1;
+        }
+    }
+    prune_empty_dirs(&get_todo_path()?)?;
+    println!("✅ Fixed {fixed} of {} problem(s)", problems.len());
+    Ok(())
+}
+
+fn diagnose() -> AnyResult<Vec<Problem>> {
+    let todo_path = get_todo_path()?;
+    let config = load_config()?.unwrap_or_default();
+    let filename = config.filename + &config.extension;
+
+    let mut files = Vec::new();
+    if todo_path.exists() {
+        collect_files(&todo_path, &mut files)?;
+    }
+    let mut problems = Vec::new();
+    for file in files {
+        let store_dir = file.parent().unwrap();
+        let dir = get_original_dir(store_dir)?;
+        if fs::read_to_string(&file).is_ok_and(|content| content.trim().is_empty()) {
+            problems.push(Problem::Empty { file });
+        } else if let Some(dir) = dir.filter(|dir| !dir.exists()) {
+            problems.push(Problem::Orphaned { file, dir });
+        } else if file.file_name().is_some_and(|name| *name != *filename) {
+            let expected = store_dir.join(&filename);
+            problems.push(Problem::Misnamed { file, expected });
+        }
+    }
+    let records = load_sync_records().unwrap_or_default();
+    let expected = get_store_todo_file_path()?;
+    if !expected.exists() {
+        let legacy = get_legacy_todo_dirs()?
+            .into_iter()
+            .map(|dir| dir.join(&filename))
+            .filter(|file| !file.starts_with(&todo_path) && file.is_file())
+            // A synced file is where it belongs
+            .find(|file| !records.iter().any(|record| record.target == *file));
+        if let Some(file) = legacy {
+            problems.push(Problem::Legacy { file, expected });
+        }
+    }
+    match load_sync_records() {
+        Ok(records) => {
+            for record in records {
+                let status = sync_status(&record);
+                if status != SyncStatus::Intact {
+                    problems.push(Problem::BrokenSync { record, status });
+                }
+            }
+        }
+        Err(error) => problems.push(Problem::UnreadableSyncs {
+            reason: format!("{error:#}"),
+        }),
+    }
+    Ok(problems)
+}
+
+/// Fixes `problem`, asking what to do with orphaned todo files. Returns whether it
+/// was fixed.
+fn fix_problem(problem: &Problem) -> AnyResult<bool> {
+    match problem {
+        // An earlier fix already moved or removed it
+        Problem::Orphaned { file, .. }
+        | Problem::Empty { file }
+        | Problem::Misnamed { file, .. }
+            if !file.exists() =>
+        {
+            Ok(true)
+        }
+        Problem::Empty { file } => {
+            let id = trash(file)?;
+            println!(
+                "✅ Removed {}, restore it with `todo trash restore {id}`",
+                file.display()
+            );
+            Ok(true)
+        }
+        Problem::Misnamed { file, expected } => {
+            if expected.exists() {
+                merge_into(file, expected)?;
+                trash(file)?;
+                println!("✅ Merged {} into {}", file.display(), expected.display());
+            } else {
+                fs::rename(file, expected)
+                    .with_context(|| format!("Failed to rename {}", file.display()))?;
+                println!("✅ Renamed {} to {}", file.display(), expected.display());
+            }
+            Ok(true)
+        }
+        Problem::Orphaned { file, .. } if !can_prompt() => {
+            println!(
+                "⚠️ Kept {}, run `todo doctor --fix` in a terminal to delete or move it",
+                file.display()
+            );
+            Ok(false)
+        }
+        Problem::Orphaned { file, dir } => {
+            let options = vec!["Keep it", "Delete it", "Move it to another directory"];
+            let choice = Select::new(&format!("{problem}"), options)
+                .prompt()
+                .context("Prompt failed")?;
+            match choice {
+                "Delete it" => {
+                    let id = trash(file)?;
+                    println!(
+                        "✅ Removed {}, restore it with `todo trash restore {id}`",
+                        file.display()
+                    );
+                    Ok(true)
+                }
+                "Move it to another directory" => {
+                    let to = Text::new("New directory:")
+                        .prompt()
+                        .context("Failed to get the directory")?;
+                    relocate(dir, Path::new(&to))?;
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+        Problem::Legacy { file, expected } => {
+            move_tree(file, expected)?;
+            println!("✅ Moved {} to {}", file.display(), expected.display());
+            Ok(true)
+        }
+        Problem::UnreadableSyncs { .. } => {
+            println!("⚠️ Fix or remove the sync records by hand, then run `todo doctor` again");
+            Ok(false)
+        }
+        Problem::BrokenSync {
+            record,
+            status: SyncStatus::Conflicted,
+        } => {
+            println!(
+                "⚠️ Resolve the conflict markers in {} or its todo file by hand",
+                record.target.display()
+            );
+            Ok(false)
+        }
+        Problem::BrokenSync { record, status } => {
+            let project_exists = record.target.parent().is_some_and(Path::exists);
+            if !project_exists || (*status == SyncStatus::SourceMissing && !record.target.exists())
+            {
+                let mut syncs = load_sync_records()?;
+                syncs.retain(|r| r != record);
+                save_sync_records(syncs)?;
+                println!("✅ Forgot the sync of {}", record.target.display());
+            } else {
+                repair_record(record)?;
+                println!("✅ Repaired {}", record.target.display());
+            }
+            Ok(true)
+        }
+    }
+}
+
+/// Removes the directories under `dir` that are left without any todo file. Returns
+/// whether `dir` itself ended up empty.
+fn prune_empty_dirs(dir: &Path) -> AnyResult<bool> {
+    if !dir.exists() {
+        return Ok(false);
+    }
+    let mut empty = true;
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() && prune_empty_dirs(&path)? {
+            fs::remove_dir(&path)?;
+        } else {
+            empty = false;
+        }
+    }
+    Ok(empty)
+}
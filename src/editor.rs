@@ -0,0 +1,140 @@
+use crate::{
+    config::{configure, load_config, Config},
+    error::TodoError,
+    utils::resolve_editor,
+};
+use anyhow::{Context, Result as AnyResult};
+use std::{path::Path, process::Command};
+use which::which;
+
+/// Opens `path` in the configured editor and waits for it to exit. With a `line`, the
+/// editor is asked to put the cursor on it, 1-based.
+///
+/// The editor is a command split like a shell would, so it can carry arguments and
+/// quotes. When it mentions `{file}` or `{line}` it's used as a template, with both
+/// replaced and the file appended when it has no `{file}`, otherwise the `flags` from the
+/// config and the file are appended to it. Templates carry their own flags.
+pub fn open_editor(path: &Path, line: Option<usize>) -> AnyResult<()> {
+    let config = match load_config()? {
+        Some(config) => config,
+        None => configure(false)?,
+    };
+    let editor = resolve_editor(editor_for(&config, path))?;
+    let mut words = shell_words::split(&editor)
+        .with_context(|| format!("Invalid editor command `{editor}`"))?;
+    if words.is_empty() {
+        return Err(TodoError::EditorMissing { editor }.into());
+    }
+    let program = words.remove(0);
+
+    let file = path.display().to_string();
+    let mut args: Vec<String> = if words
+        .iter()
+        .any(|word| word.contains("{file}") || word.contains("{line}"))
+    {
+        let has_file = words.iter().any(|word| word.contains("{file}"));
+        let line = line.unwrap_or(1).to_string();
+        words
+            .iter()
+            .map(|word| word.replace("{file}", &file).replace("{line}", &line))
+            .chain((!has_file).then_some(file.clone()))
+            .collect()
+    } else {
+        let target = match line {
+            Some(line) => line_args(&config, &program, path, line),
+            None => vec![file],
+        };
+        // Flags go before the file, where editors expect options
+        words
+            .into_iter()
+            .chain(config.flags.iter().cloned())
+            .chain(target)
+            .collect()
+    };
+    add_wait_flag(&program, &mut args);
+
+    Command::new(which(&program).map_err(|_| TodoError::EditorMissing {
+        editor: program.clone(),
+    })?)
+    .args(args)
+    .spawn()
+    .with_context(|| format!("Failed to launch editor `{program}`"))?
+    .wait()
+    .context("Editor process failed")?;
+    Ok(())
+}
+
+/// The editor for `path`, from `editors` in the config when its extension is there
+fn editor_for(config: &Config, path: &Path) -> String {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    config
+        .editors
+        .iter()
+        .find(|(key, _)| {
+            extension
+                .as_deref()
+                .is_some_and(|extension| key.trim_start_matches('.').to_lowercase() == extension)
+        })
+        .map_or_else(|| config.editor.clone(), |(_, editor)| editor.clone())
+}
+
+/// The name of an editor's program, e.g. `code` for `/usr/bin/code.cmd`
+fn editor_name(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// The arguments opening `path` at `line`, from `line_args` in the config or, when that's
+/// empty, from the conventions of well known editors
+fn line_args(config: &Config, program: &str, path: &Path, line: usize) -> Vec<String> {
+    let file = path.display().to_string();
+    if !config.line_args.is_empty() {
+        return config
+            .line_args
+            .iter()
+            .map(|arg| {
+                arg.replace("{file}", &file)
+                    .replace("{line}", &line.to_string())
+            })
+            .collect();
+    }
+    match editor_name(program).as_str() {
+        "vi" | "vim" | "nvim" | "gvim" | "nano" | "pico" | "emacs" | "emacsclient" | "micro"
+        | "kak" | "joe" | "ne" | "mg" | "gedit" => vec![format!("+{line}"), file],
+        "code" | "code-insiders" | "codium" | "cursor" | "windsurf" => {
+            vec!["-g".into(), format!("{file}:{line}")]
+        }
+        "subl" | "hx" | "helix" | "zed" => vec![format!("{file}:{line}")],
+        "kate" => vec!["-l".into(), line.to_string(), file],
+        "notepad++" => vec![format!("-n{line}"), file],
+        _ => {
+            eprintln!(
+                "ℹ Don't know how to open `{program}` at a line, set `line_args` in the config"
+            );
+            vec![file]
+        }
+    }
+}
+
+/// GUI editors return as soon as the window opens unless told to wait, which would run
+/// the sync merge before the todos were edited
+fn add_wait_flag(program: &str, args: &mut Vec<String>) {
+    let (flag, aliases): (&str, &[&str]) = match editor_name(program).as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" | "windsurf" | "subl" | "zed" => {
+            ("--wait", &["-w"])
+        }
+        "gvim" | "mvim" => ("-f", &["--nofork"]),
+        "mate" => ("-w", &["--wait"]),
+        _ => return,
+    };
+    if !args
+        .iter()
+        .any(|arg| arg == flag || aliases.contains(&arg.as_str()))
+    {
+        args.insert(0, flag.to_string());
+    }
+}
@@ -0,0 +1,90 @@
+use std::{fmt, io, path::PathBuf};
+
+/// Failures scripts may want to react to, each with its own exit code:
+///
+/// | Code | Meaning                                   |
+/// |------|-------------------------------------------|
+/// | 1    | any other failure                         |
+/// | 2    | invalid arguments                         |
+/// | 3    | no todo file for the directory            |
+/// | 4    | no todo matches the query                 |
+/// | 5    | several todos match the query             |
+/// | 6    | input needed but prompting isn't possible |
+/// | 7    | invalid config file                       |
+/// | 8    | editor not set or not found               |
+/// | 9    | reading or writing a file failed          |
+#[derive(Debug)]
+pub enum TodoError {
+    NoTodoFile,
+    NoMatch { query: String },
+    AmbiguousMatch { query: String, matches: Vec<String> },
+    InputRequired { hint: String },
+    ConfigInvalid { path: PathBuf, reason: String },
+    EditorMissing { editor: String },
+}
+
+const IO_EXIT_CODE: i32 = 9;
+
+impl TodoError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TodoError::NoTodoFile => 3,
+            TodoError::NoMatch { .. } => 4,
+            TodoError::AmbiguousMatch { .. } => 5,
+            TodoError::InputRequired { .. } => 6,
+            TodoError::ConfigInvalid { .. } => 7,
+            TodoError::EditorMissing { .. } => 8,
+        }
+    }
+}
+
+impl fmt::Display for TodoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TodoError::NoTodoFile => write!(
+                f,
+                "No todo file found for this directory. Run `todo` to create one."
+            ),
+            TodoError::NoMatch { query } => write!(f, "No todos match `{query}`"),
+            TodoError::AmbiguousMatch { query, matches } => {
+                writeln!(f, "`{query}` matches {} todos equally well:", matches.len())?;
+                for text in matches {
+                    writeln!(f, "  {text}")?;
+                }
+                write!(f, "Use a more specific query")
+            }
+            TodoError::InputRequired { hint } => {
+                write!(f, "Can't prompt without an interactive terminal. {hint}")
+            }
+            TodoError::ConfigInvalid { path, reason } => {
+                write!(f, "Invalid config at {}: {reason}", path.display())
+            }
+            TodoError::EditorMissing { editor } => match editor.strip_prefix('$') {
+                Some(var) => write!(
+                    f,
+                    "Environment variable `{var}` is not set. Please set it or update your config."
+                ),
+                None => write!(
+                    f,
+                    "Could not find the editor binary `{editor}`. Please check your config or PATH."
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for TodoError {}
+
+/// The exit code for `error`, see [`TodoError`].
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    if let Some(error) = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<TodoError>())
+    {
+        return error.exit_code();
+    }
+    if error.chain().any(|cause| cause.is::<io::Error>()) {
+        return IO_EXIT_CODE;
+    }
+    1
+}
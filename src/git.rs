@@ -0,0 +1,66 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Finds the root of the git repository containing `dir` and the url of its `origin`
+/// remote, reading `.git/config` directly.
+pub fn find_origin(dir: &Path) -> Option<(PathBuf, String)> {
+    let (root, git_dir) = dir.ancestors().find_map(|dir| {
+        let dot_git = dir.join(".git");
+        if dot_git.is_dir() {
+            Some((dir.to_path_buf(), dot_git))
+        } else if dot_git.is_file() {
+            // Worktrees and submodules have a `.git` file pointing to the real git dir
+            let content = fs::read_to_string(&dot_git).ok()?;
+            let git_dir = dir.join(content.strip_prefix("gitdir:")?.trim());
+            Some((dir.to_path_buf(), git_dir))
+        } else {
+            None
+        }
+    })?;
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common_dir) => git_dir.join(common_dir.trim()),
+        Err(_) => git_dir,
+    };
+    let config = fs::read_to_string(common_dir.join("config")).ok()?;
+    origin_url(&config).map(|url| (root, url))
+}
+
+fn origin_url(config: &str) -> Option<String> {
+    let mut in_origin = false;
+    for line in config.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_origin = line == r#"[remote "origin"]"#;
+        } else if in_origin {
+            if let Some((key, value)) = line.split_once('=') {
+                if key.trim() == "url" {
+                    return Some(value.trim().trim_matches('"').to_string());
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Turns the different spellings of a remote url into the same relative path, e.g.
+/// `git@github.com:user/repo.git` and `https://github.com/user/repo` both become
+/// `github.com/user/repo`.
+pub fn normalize_remote(url: &str) -> Option<PathBuf> {
+    let url = url.trim().trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let (host, path) = match url.split_once("://") {
+        Some((_, rest)) => rest.split_once('/').unwrap_or((rest, "")),
+        // scp-like syntax, `user@host:path`
+        None if !url.starts_with('/') && url.contains(':') => url.split_once(':')?,
+        None => ("", url),
+    };
+    let host = host.rsplit('@').next()?;
+    let host = host.split(':').next()?.to_lowercase();
+    let normalized: PathBuf = [host.as_str()]
+        .into_iter()
+        .chain(path.split('/'))
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .collect();
+    (normalized.components().count() > 0).then_some(normalized)
+}
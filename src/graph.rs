@@ -0,0 +1,158 @@
+use crate::{
+    attributes::{blockers, id},
+    state::{states, State},
+    todo_file::TodoFile,
+    utils::get_todo_file_path,
+};
+use anyhow::Result as AnyResult;
+use std::collections::{HashMap, HashSet};
+
+struct Node {
+    number: usize,
+    line: String,
+    text: String,
+    open: bool,
+    id: Option<String>,
+    blockers: Vec<String>,
+}
+
+/// Prints the todos that block or are blocked by others, each followed by the todos
+/// waiting for it, or the same graph in the DOT format.
+pub fn graph(dot: bool) -> AnyResult<()> {
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let regex = states().regex();
+    let nodes: Vec<Node> = file
+        .todo_lines()
+        .into_iter()
+        .enumerate()
+        .filter_map(|(number, i)| {
+            let line = file.line(i)?;
+            let caps = regex.captures(line)?;
+            let text = caps.get(2)?.as_str();
+            Some(Node {
+                number: number + 1,
+                line: line.trim().to_string(),
+                open: states().state(&caps[1]).is_some_and(State::is_open),
+                text: text.trim().to_string(),
+                id: id(text).map(String::from),
+                blockers: blockers(text).into_iter().map(String::from).collect(),
+            })
+        })
+        .collect();
+
+    let by_id: HashMap<&str, usize> = nodes
+        .iter()
+        .enumerate()
+        .filter_map(|(k, node)| Some((node.id.as_deref()?, k)))
+        .collect();
+    let mut dependents: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (k, node) in nodes.iter().enumerate() {
+        for blocker in &node.blockers {
+            match by_id.get(blocker.as_str()) {
+                Some(&blocker) => dependents.entry(blocker).or_default().push(k),
+                None if !dot => {
+                    eprintln!("⚠️ `{}` waits for a missing todo id:{blocker}", node.line)
+                }
+                None => {}
+            }
+        }
+    }
+    if dependents.is_empty() {
+        println!("No todos block others. Add `blocked-by:<id>` to a todo to make it wait.");
+        return Ok(());
+    }
+
+    if dot {
+        print_dot(&nodes, &dependents);
+        return Ok(());
+    }
+
+    // Start from the todos that don't wait for anything, then from whatever is left,
+    // which only happens with cycles
+    let waits = |k: &usize| {
+        nodes[*k]
+            .blockers
+            .iter()
+            .any(|blocker| by_id.contains_key(blocker.as_str()))
+    };
+    let linked = (0..nodes.len()).filter(|k| dependents.contains_key(k));
+    let roots: Vec<usize> = linked.clone().filter(|k| !waits(k)).chain(linked).collect();
+    let graph = Graph { nodes, dependents };
+    let mut printed = HashSet::new();
+    for root in roots {
+        if !printed.contains(&root) {
+            graph.print_tree(root, 0, &mut Vec::new(), &mut printed);
+        }
+    }
+    Ok(())
+}
+
+struct Graph {
+    nodes: Vec<Node>,
+    /// The todos waiting for each todo
+    dependents: HashMap<usize, Vec<usize>>,
+}
+
+impl Graph {
+    fn print_tree(
+        &self,
+        k: usize,
+        depth: usize,
+        path: &mut Vec<usize>,
+        printed: &mut HashSet<usize>,
+    ) {
+        let prefix = match depth {
+            0 => String::new(),
+            _ => format!("{}└─ ", "   ".repeat(depth - 1)),
+        };
+        let node = &self.nodes[k];
+        if path.contains(&k) {
+            println!("{prefix}{}  {} (cycle)", node.number, node.line);
+            return;
+        }
+        println!("{prefix}{}  {}", node.number, node.line);
+        printed.insert(k);
+        path.push(k);
+        for &dependent in self.dependents.get(&k).into_iter().flatten() {
+            self.print_tree(dependent, depth + 1, path, printed);
+        }
+        path.pop();
+    }
+}
+
+fn print_dot(nodes: &[Node], dependents: &HashMap<usize, Vec<usize>>) {
+    let name = |node: &Node| match &node.id {
+        Some(id) => id.clone(),
+        None => format!("todo{}", node.number),
+    };
+    let linked: HashSet<usize> = dependents
+        .iter()
+        .flat_map(|(&blocker, blocked)| blocked.iter().copied().chain([blocker]))
+        .collect();
+    println!("digraph todos {{");
+    println!("  rankdir=LR;");
+    for (k, node) in nodes.iter().enumerate() {
+        if !linked.contains(&k) {
+            continue;
+        }
+        let label = node.text.replace('\\', "\\\\").replace('"', "\\\"");
+        let style = if !node.open {
+            ", style=dashed, fontcolor=gray"
+        } else {
+            ""
+        };
+        println!("  \"{}\" [label=\"{label}\"{style}];", name(node));
+    }
+    let mut edges: Vec<_> = dependents.iter().collect();
+    edges.sort();
+    for (&blocker, blocked) in edges {
+        for &k in blocked {
+            println!(
+                "  \"{}\" -> \"{}\";",
+                name(&nodes[blocker]),
+                name(&nodes[k])
+            );
+        }
+    }
+    println!("}}");
+}
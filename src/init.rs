@@ -0,0 +1,53 @@
+use crate::{
+    cli::InitArgs,
+    config::{load_config, LocalConfig},
+    template::create_todo_file,
+    utils::{get_store_todo_file_path, get_todo_file_path, LOCAL_MARKER},
+};
+use anyhow::{Context, Result as AnyResult};
+use std::{env, fs, path::PathBuf};
+
+pub fn init(args: InitArgs) -> AnyResult<()> {
+    if !args.local {
+        let path = get_todo_file_path()?;
+        if path.exists() {
+            println!("ℹ {} already exists", path.display());
+            return Ok(());
+        }
+        create_todo_file(&path, None)?;
+        println!("✅ Created {}", path.display());
+        return Ok(());
+    }
+
+    let cwd = env::current_dir()?;
+    let marker = cwd.join(LOCAL_MARKER);
+    if marker.exists() {
+        println!("⚠️ This directory already keeps its todos locally");
+        return Ok(());
+    }
+    let config = load_config()?.unwrap_or_default();
+    let path = args
+        .path
+        .unwrap_or_else(|| PathBuf::from(config.filename + &config.extension));
+    let local_file = cwd.join(&path);
+    if let Some(parent) = local_file.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create {parent:?}"))?;
+    }
+    if !local_file.exists() {
+        let store_file = get_store_todo_file_path()?;
+        if store_file.exists() {
+            fs::copy(&store_file, &local_file).context("Failed to move the existing todos")?;
+            fs::remove_file(&store_file).context("Failed to move the existing todos")?;
+            println!("✅ Moved the existing todos from {}", store_file.display());
+        } else {
+            create_todo_file(&local_file, None)?;
+        }
+    }
+    fs::write(&marker, toml::to_string(&LocalConfig { path })?)
+        .with_context(|| format!("Failed to write {}", marker.display()))?;
+    println!(
+        "✅ Todos for this directory now live in {}",
+        local_file.display()
+    );
+    Ok(())
+}
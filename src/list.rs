@@ -1,10 +1,17 @@
 use crate::{
-    cli::{ListArgs, ListSubcommand},
+    attributes::{blockers, due, priority},
+    cli::{ListArgs, ListSubcommand, SortKey},
+    state::{states, State},
+    todo_file::{TodoFile, HEADING_PATTERN},
     utils::get_todo_file_path,
 };
-use anyhow::{anyhow, Result as AnyResult};
+use anyhow::Result as AnyResult;
+use crossterm::style::Stylize;
 use regex::Regex;
-use std::{fs, io};
+use std::{
+    collections::HashMap,
+    io::{self, IsTerminal},
+};
 
 pub enum ListMode {
     Raw,
@@ -25,9 +32,6 @@ impl ListMode {
 
 impl From<ListArgs> for ListMode {
     fn from(value: ListArgs) -> Self {
-        if value.raw && value.filter.is_some() {
-            panic!("raw cannot be used with filters")
-        }
         if value.raw {
             return Self::Raw;
         }
@@ -39,25 +43,94 @@ impl From<ListArgs> for ListMode {
     }
 }
 
-pub fn list(mode: ListMode) -> AnyResult<()> {
+pub fn list(args: ListArgs) -> AnyResult<()> {
+    let (section, priority_filter, sort) = (args.section.clone(), args.priority, args.sort);
+    let mode = ListMode::from(args);
     if mode.is_raw() {
-        return list_raw();
+        return list_raw(section);
     }
-    let regex = match mode {
-        ListMode::All => r"^\s*[-*+]? ?\[( |x)\](.+)$",
-        ListMode::Checked => r"^\s*[-*+]? ?\[(x)\](.+)$",
-        ListMode::Unchecked => r"^\s*[-*+]? ?\[( )\](.+)$",
+    let regex = states().regex_for(|state| match mode {
+        ListMode::All => true,
+        ListMode::Checked => !state.is_open(),
+        ListMode::Unchecked => state.is_open(),
         _ => unreachable!("handled by is_raw"),
-    };
-    let regex = Regex::new(regex).unwrap();
-    let content = fs::read_to_string(get_todo_file_path()?)?;
+    });
+    let heading_regex = Regex::new(HEADING_PATTERN).unwrap();
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let scope = file.scope(section.as_deref())?;
 
-    let todos: Vec<&str> = content
-        .lines()
-        .filter_map(|line| regex.captures(line).map(|_| line.trim_end()))
+    // Numbered among all todos, so that the numbers work with `todo check` and friends
+    let numbers: HashMap<usize, usize> = file
+        .todo_lines()
+        .into_iter()
+        .enumerate()
+        .map(|(number, i)| (i, number + 1))
         .collect();
+    let width = numbers.len().to_string().len();
+
+    // Headings are only shown once a todo under them is
+    let mut headings: Vec<(usize, &str, bool)> = Vec::new();
+    let open = file.open_ids();
+    let dim = io::stdout().is_terminal();
+    let mut output = Vec::new();
+    let mut todos = Vec::new();
+    for (i, line) in file.lines_outside_code() {
+        if let Some(caps) = heading_regex.captures(line) {
+            let level = caps[1].len();
+            headings.retain(|(other, _, _)| *other < level);
+            if scope.contains(&i) {
+                headings.push((level, line.trim_end(), false));
+            }
+            continue;
+        }
+        let Some(caps) = regex.captures(line).filter(|_| scope.contains(&i)) else {
+            continue;
+        };
+        let text = caps.get(2).unwrap().as_str().trim();
+        if priority_filter.is_some_and(|filter| priority(text).is_none_or(|p| p > filter)) {
+            continue;
+        }
+        let number = numbers.get(&i).copied().unwrap_or_default();
+        // Todos waiting for unchecked ones are dimmed
+        let state = states().state(&caps[1]);
+        let blocked =
+            state.is_some_and(State::is_open) && blockers(text).iter().any(|id| open.contains(*id));
+        let line = if blocked && dim {
+            line.trim_end().dim().to_string()
+        } else {
+            line.trim_end().to_string()
+        };
+        if sort.is_some() {
+            todos.push((number, line, state, text));
+            continue;
+        }
+        for (_, heading, shown) in headings.iter_mut().filter(|(_, _, shown)| !*shown) {
+            if !output.is_empty() {
+                output.push(String::new());
+            }
+            output.push(heading.to_string());
+            *shown = true;
+        }
+        output.push(format!("{number:>width$}  {line}"));
+    }
+    // Sorted todos are listed without their headings, as they no longer follow them
+    if let Some(sort) = sort {
+        match sort {
+            SortKey::State => todos.sort_by_key(|todo| todo.2),
+            SortKey::Due => todos.sort_by_key(|todo| (due(todo.3).is_none(), due(todo.3))),
+            SortKey::Priority => {
+                todos.sort_by_key(|todo| (priority(todo.3).is_none(), priority(todo.3)))
+            }
+            SortKey::Alpha => todos.sort_by_key(|todo| todo.3.to_lowercase()),
+        }
+        output.extend(
+            todos
+                .into_iter()
+                .map(|(number, line, _, _)| format!("{number:>width$}  {line}")),
+        );
+    }
 
-    if todos.is_empty() {
+    if output.is_empty() {
         match mode {
             ListMode::All => println!("No todos found."),
             ListMode::Checked => println!("No checked todos found."),
@@ -67,21 +140,16 @@ pub fn list(mode: ListMode) -> AnyResult<()> {
         return Ok(());
     }
 
-    for todo in todos {
-        println!("{todo}");
+    for line in output {
+        println!("{line}");
     }
     Ok(())
 }
 
-fn list_raw() -> AnyResult<()> {
-    match fs::read_to_string(get_todo_file_path()?) {
-        Ok(content) => println!("{}", content.trim()),
-        Err(error) if error.kind() == io::ErrorKind::NotFound => {
-            return Err(anyhow!(
-                "❌ No todo file found for this directory. Run `todo` to create one."
-            ));
-        }
-        Err(error) => return Err(anyhow!("❌ Failed to read todo file: {error}")),
-    }
+fn list_raw(section: Option<String>) -> AnyResult<()> {
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let scope = file.scope(section.as_deref())?;
+    let lines: Vec<_> = file.lines().skip(scope.start).take(scope.len()).collect();
+    println!("{}", lines.join("\n").trim());
     Ok(())
 }
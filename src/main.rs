@@ -1,41 +1,119 @@
+mod attributes;
+mod checklist;
 mod cli;
 mod config;
 mod count;
 mod delete;
+mod doctor;
+mod editor;
+mod error;
+mod git;
+mod graph;
+mod init;
 mod list;
+mod relocate;
+mod state;
 mod sync;
+mod template;
+mod todo_file;
 mod todo_ops;
+mod trash;
 mod update;
 mod utils;
 use crate::{
-    cli::{Cli, Commands, ConfigSubcommand},
-    config::{configure, load_config},
+    cli::{ChecklistSubcommand, Cli, Commands, ConfigSubcommand, SyncSubcommand, TrashSubcommand},
+    config::configure,
     delete::delete,
-    sync::{sync, unsync},
-    todo_ops::{check, remove, search, uncheck},
+    doctor::doctor,
+    editor::open_editor,
+    error::exit_code,
+    graph::graph,
+    init::init,
+    relocate::relocate,
+    sync::{merge_synced, repair, status, sync, unsync},
+    template::create_todo_file,
+    todo_ops::{
+        add, assign_ids, cancel, check, defer, edit, move_to_section, move_todo, next, open,
+        remove, search, sort, start, uncheck,
+    },
     update::update,
-    utils::{get_config_path, get_cwd_todo_dir, get_todo_file_path, resolve_editor},
+    utils::{disable_input, get_config_path, get_todo_file_path},
 };
-use anyhow::{anyhow, Result as AnyResult};
-use clap::Parser;
+use anyhow::Result as AnyResult;
+use clap::{error::ErrorKind, CommandFactory, Parser};
 use count::count;
 use list::list;
-use std::{fs, process::Command};
-use which::which;
+use std::{fs, path::Path, process::exit};
 
-fn main() -> AnyResult<()> {
+fn main() {
+    if let Err(error) = run() {
+        eprintln!("❌ {error:#}");
+        exit(exit_code(&error));
+    }
+}
+
+fn run() -> AnyResult<()> {
     let cli = Cli::parse();
+    if cli.no_input {
+        disable_input();
+    }
+    // Merged before and after the commands using the todo file, so they see the changes
+    // made to its synced copy and the copy gets theirs
+    // When that fails, it's only reported once
+    let todo_file_path = uses_todo_file(&cli.command)
+        .then(|| get_todo_file_path().ok())
+        .flatten()
+        .filter(|path| merge(path, true));
     match cli.command {
         Some(command) => match command {
             Commands::Update => update(),
-            Commands::Sync => sync(get_todo_file_path()?),
+            Commands::Init(args) => init(args),
+            Commands::New { template } => template::new(template),
+            Commands::Sync(args) => match args.action {
+                Some(SyncSubcommand::Status) => status(get_todo_file_path()?),
+                Some(SyncSubcommand::Repair) => repair(get_todo_file_path()?),
+                None => sync(get_todo_file_path()?, args.mode, args.target),
+            },
             Commands::Unsync => unsync(get_todo_file_path()?),
-            Commands::Check { query, all } => check(query.unwrap_or_default(), all),
-            Commands::Search { query } => search(query.unwrap_or_default()),
-            Commands::Uncheck { query, all } => uncheck(query.unwrap_or_default(), all),
-            Commands::Remove { query, all } => remove(query.unwrap_or_default(), all),
-            Commands::List(args) => list(args.into()),
-            Commands::Count(args) => count(args.filter.into()),
+            Commands::Check { select, all, force } => check(select, all, force),
+            Commands::Search { query, section } => search(query.unwrap_or_default(), section),
+            Commands::Uncheck { select, all } => uncheck(select, all),
+            Commands::Remove { select, all } => remove(select, all),
+            Commands::Start { select, all } => start(select, all),
+            Commands::Cancel { select, all } => cancel(select, all),
+            Commands::Defer { select, all } => defer(select, all),
+            Commands::List(args) => {
+                // clap can't make an argument conflict with subcommands only
+                if args.raw && args.filter.is_some() {
+                    let mut cli = Cli::command();
+                    cli.build();
+                    cli.find_subcommand_mut("list")
+                        .expect("list is a subcommand")
+                        .error(
+                            ErrorKind::ArgumentConflict,
+                            "--raw can't be used with `checked` or `unchecked`",
+                        )
+                        .exit();
+                }
+                list(args)
+            }
+            Commands::Edit { query, text } => edit(query, text),
+            Commands::Open { query } => open(query),
+            Commands::Add { text, section } => add(text, section),
+            Commands::Move {
+                query,
+                position,
+                to_section,
+            } => match (position, to_section) {
+                (Some(position), _) => move_todo(query, position),
+                (None, Some(section)) => move_to_section(query, section),
+                (None, None) => unreachable!("required by clap"),
+            },
+            Commands::Sort { by, section } => sort(by, section),
+            Commands::Next { global } => next(global),
+            Commands::Graph { dot } => graph(dot),
+            Commands::Ids => assign_ids(),
+            Commands::Count(args) => count(args.filter.into(), args.section),
             Commands::Config(args) => {
                 match args.action {
                     Some(ConfigSubcommand::List) => {
@@ -53,28 +131,70 @@ fn main() -> AnyResult<()> {
                 Ok(())
             }
             Commands::Delete(args) => delete(args),
+            Commands::Trash(args) => match args.action {
+                TrashSubcommand::List => trash::list(),
+                TrashSubcommand::Restore { id } => trash::restore(&id),
+                TrashSubcommand::Empty { older_than } => trash::empty(older_than),
+            },
+            Commands::Checklist(args) => match args.action {
+                ChecklistSubcommand::Save {
+                    name,
+                    section,
+                    from,
+                    yes,
+                } => checklist::save(&name, section, from, yes),
+                ChecklistSubcommand::List => checklist::list(),
+                ChecklistSubcommand::Apply { name, under, from } => {
+                    checklist::apply(&name, under, from)
+                }
+            },
+            Commands::Mv { from, to } => relocate(&from, &to),
+            Commands::Doctor { fix } => doctor(fix),
         },
         None => {
-            let cwd_todo_dir = get_cwd_todo_dir()?;
-            fs::create_dir_all(&cwd_todo_dir)
-                .map_err(|e| anyhow!("❌ Failed to create todo directory: {e}"))?;
-            let config = load_config()?;
-            let config = match config {
-                Some(config) => config,
-                None => configure(false)?,
-            };
-            let editor = resolve_editor(config.editor)?;
-            Command::new(
-                which(&editor).map_err(|_| anyhow!("❌ Could not find the editor binary `{editor}`. Please check your config or PATH."))?,
-            )
-            .arg(get_todo_file_path()?)
-            .args(config.flags)
-            .spawn()
-            .map_err(|e| anyhow!("❌ Failed to launch editor `{editor}`: {e}"))?
-            .wait()
-            .map_err(|e| anyhow!("❌ Editor process failed: {e}"))?;
+            let todo_file_path = get_todo_file_path()?;
+            if !todo_file_path.exists() {
+                create_todo_file(&todo_file_path, None)?;
+            }
+            open_editor(&todo_file_path, None)?;
             Ok(())
         }
     }?;
+    if let Some(path) = &todo_file_path {
+        merge(path, false);
+    }
     Ok(())
 }
+
+/// Whether a command reads or writes the todo file of the current directory. The others
+/// manage the store, the config or the syncs themselves, and must work when those are
+/// broken.
+fn uses_todo_file(command: &Option<Commands>) -> bool {
+    match command {
+        Some(
+            Commands::Update
+            | Commands::Init(_)
+            | Commands::Sync(_)
+            | Commands::Unsync
+            | Commands::Config(_)
+            | Commands::Delete(_)
+            | Commands::Trash(_)
+            | Commands::Mv { .. }
+            | Commands::Doctor { .. },
+        ) => false,
+        Some(Commands::Checklist(args)) => !matches!(args.action, ChecklistSubcommand::List),
+        _ => true,
+    }
+}
+
+/// Merges the todo file with its synced copy, only warning when that fails so the
+/// command still runs. Returns whether the merge worked.
+fn merge(path: &Path, remind: bool) -> bool {
+    match merge_synced(path, remind) {
+        Ok(()) => true,
+        Err(error) => {
+            eprintln!("⚠️ Couldn't merge the synced copy of the todo file: {error:#}");
+            false
+        }
+    }
+}
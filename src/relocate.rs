@@ -0,0 +1,87 @@
+use crate::{
+    sync::relocate_syncs,
+    todo_file::TodoFile,
+    utils::{absolute_path, get_todo_dir},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use std::{collections::HashSet, fs, path::Path};
+
+pub fn relocate(from: &Path, to: &Path) -> AnyResult<()> {
+    let (from, to) = (absolute_path(from)?, absolute_path(to)?);
+    let from_store = get_todo_dir(&from)?;
+    let to_store = get_todo_dir(&to)?;
+    if from_store == to_store {
+        return Err(anyhow!(
+            "{} and {} already share their todos",
+            from.display(),
+            to.display()
+        ));
+    }
+    if !from_store.exists() {
+        return Err(anyhow!("There are no todos for {}", from.display()));
+    }
+    if to_store.starts_with(&from_store) {
+        return Err(anyhow!(
+            "Can't move todos into a subdirectory of themselves"
+        ));
+    }
+
+    let (moved, merged) = move_tree(&from_store, &to_store)?;
+    let relinked = relocate_syncs(&from_store, &to_store, &from, &to)?;
+    println!(
+        "✅ Moved {moved} todo file(s) from {} to {}",
+        from.display(),
+        to.display()
+    );
+    if merged > 0 {
+        println!("ℹ Merged {merged} of them into existing todo files");
+    }
+    if relinked > 0 {
+        println!("ℹ Updated {relinked} synced file(s)");
+    }
+    Ok(())
+}
+
+/// Moves every todo file under `from` to the same place under `to`, merging them into
+/// the ones that already exist there. Returns how many files were moved and merged.
+pub fn move_tree(from: &Path, to: &Path) -> AnyResult<(usize, usize)> {
+    if !to.exists() {
+        fs::create_dir_all(to.parent().unwrap()).context("Failed to create todo directory")?;
+    }
+    let (mut moved, mut merged) = (0, 0);
+    if from.is_dir() {
+        fs::create_dir_all(to).context("Failed to create todo directory")?;
+        for entry in fs::read_dir(from)? {
+            let path = entry?.path();
+            let (dir_moved, dir_merged) = move_tree(&path, &to.join(path.file_name().unwrap()))?;
+            moved += dir_moved;
+            merged += dir_merged;
+        }
+        fs::remove_dir(from).with_context(|| format!("Failed to remove {}", from.display()))?;
+    } else if to.exists() {
+        merge_into(from, to)?;
+        fs::remove_file(from).with_context(|| format!("Failed to remove {}", from.display()))?;
+        moved += 1;
+        merged += 1;
+    } else {
+        // Local todo files may live on another filesystem than the store
+        fs::rename(from, to)
+            .or_else(|_| fs::copy(from, to).and_then(|_| fs::remove_file(from)))
+            .with_context(|| format!("Failed to move {}", from.display()))?;
+        moved += 1;
+    }
+    Ok((moved, merged))
+}
+
+/// Appends the lines of `from` that `into` doesn't have yet.
+pub fn merge_into(from: &Path, into: &Path) -> AnyResult<()> {
+    let source = TodoFile::read(from)?;
+    let mut destination = TodoFile::read(into)?;
+    let existing: HashSet<String> = destination.lines().map(str::to_string).collect();
+    for line in source.lines() {
+        if !line.trim().is_empty() && !existing.contains(line) {
+            destination.push_line(line.to_string());
+        }
+    }
+    destination.write(into)
+}
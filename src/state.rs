@@ -0,0 +1,148 @@
+use crate::config::load_config;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, sync::OnceLock};
+
+static STATES: OnceLock<States> = OnceLock::new();
+
+/// What the checkbox of a todo says about it, in the order `todo sort --by state` uses
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum State {
+    Doing,
+    Todo,
+    Deferred,
+    Done,
+    Cancelled,
+}
+
+impl State {
+    pub const ALL: [State; 5] = [
+        State::Todo,
+        State::Doing,
+        State::Deferred,
+        State::Done,
+        State::Cancelled,
+    ];
+
+    /// Whether the todo still has to be done. Open todos are "unchecked" and block the
+    /// todos waiting for them.
+    pub fn is_open(self) -> bool {
+        matches!(self, State::Todo | State::Doing | State::Deferred)
+    }
+
+    fn default_marker(self) -> char {
+        match self {
+            State::Todo => ' ',
+            State::Doing => '/',
+            State::Deferred => '>',
+            State::Done => 'x',
+            State::Cancelled => '-',
+        }
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            State::Todo => "todo",
+            State::Doing => "doing",
+            State::Deferred => "deferred",
+            State::Done => "done",
+            State::Cancelled => "cancelled",
+        })
+    }
+}
+
+/// The checkbox markers understood by default, like Obsidian and GitHub do
+pub fn default_states() -> BTreeMap<String, State> {
+    [
+        (" ", State::Todo),
+        ("/", State::Doing),
+        (">", State::Deferred),
+        ("x", State::Done),
+        ("X", State::Done),
+        ("-", State::Cancelled),
+        ("~", State::Cancelled),
+    ]
+    .into_iter()
+    .map(|(marker, state)| (marker.to_string(), state))
+    .collect()
+}
+
+/// The checkbox markers in use and the state each one stands for
+pub struct States {
+    markers: BTreeMap<char, State>,
+}
+
+/// The states from the config, or the default ones when there's no valid config
+pub fn states() -> &'static States {
+    STATES.get_or_init(|| {
+        let states = load_config()
+            .ok()
+            .flatten()
+            .map_or_else(default_states, |config| config.states);
+        States {
+            markers: states
+                .into_iter()
+                .filter_map(|(marker, state)| Some((marker.chars().next()?, state)))
+                .collect(),
+        }
+    })
+}
+
+impl States {
+    /// Matches a todo in any state, capturing its marker and its text
+    pub fn regex(&self) -> Regex {
+        self.regex_for(|_| true)
+    }
+
+    /// Matches a todo in one of the states `filter` accepts, capturing its marker and
+    /// its text
+    pub fn regex_for(&self, filter: impl Fn(State) -> bool) -> Regex {
+        let markers: String = self
+            .markers
+            .iter()
+            .filter(|(_, state)| filter(**state))
+            .map(|(marker, _)| regex::escape(&marker.to_string()))
+            .collect();
+        if markers.is_empty() {
+            // Matches nothing
+            return Regex::new(r"[^\s\S]").unwrap();
+        }
+        // A checkbox starting a bullet or an ordered list item, possibly quoted, or on its own
+        Regex::new(&format!(
+            r"^\s*(?:>\s*)*(?:[-*+]|\d{{1,9}}[.)])? ?\[([{markers}])\](\s.*\S.*)$"
+        ))
+        .unwrap()
+    }
+
+    pub fn state(&self, marker: &str) -> Option<State> {
+        self.markers.get(&marker.chars().next()?).copied()
+    }
+
+    /// The marker written for `state`: the usual one when it's configured, otherwise the
+    /// first one configured for it
+    pub fn marker(&self, state: State) -> char {
+        if self.markers.get(&state.default_marker()) == Some(&state) {
+            return state.default_marker();
+        }
+        self.markers
+            .iter()
+            .find(|(_, other)| **other == state)
+            .map_or(state.default_marker(), |(marker, _)| *marker)
+    }
+
+    /// A todo line with its checkbox set to `state`
+    pub fn set(&self, line: &str, state: State) -> String {
+        match self.regex().captures(line).and_then(|caps| caps.get(1)) {
+            Some(marker) => format!(
+                "{}{}{}",
+                &line[..marker.start()],
+                self.marker(state),
+                &line[marker.end()..]
+            ),
+            None => line.to_string(),
+        }
+    }
+}
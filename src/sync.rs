@@ -1,30 +1,509 @@
-use anyhow::{anyhow, Result as AnyResult};
-use std::{fs, path::PathBuf};
+use crate::{
+    cli::SyncMode,
+    error::TodoError,
+    relocate::merge_into,
+    trash::trash,
+    utils::{get_sync_bases_path, get_syncs_path, get_todo_path},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use diffy::{ConflictStyle, MergeOptions};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt, fs, io,
+    path::{Component, Path, PathBuf},
+};
 
-pub fn sync(original: PathBuf) -> AnyResult<()> {
-    let link = original
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    /// The todo file in the store
+    pub source: PathBuf,
+    /// The synced file in the project
+    pub target: PathBuf,
+    pub mode: SyncMode,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncRecords {
+    #[serde(default)]
+    syncs: Vec<SyncRecord>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// The synced file is linked to (or identical with) the todo file
+    Intact,
+    /// The synced file doesn't exist anymore
+    Missing,
+    /// The todo file in the store doesn't exist anymore
+    SourceMissing,
+    /// The synced file exists but is no longer linked to the todo file,
+    /// e.g. because an editor replaced it or it was copied across filesystems
+    Detached,
+    /// The copy differs from the todo file
+    Stale,
+    /// A merge left conflict markers that weren't resolved yet
+    Conflicted,
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SyncStatus::Intact => "intact",
+            SyncStatus::Missing => "missing",
+            SyncStatus::SourceMissing => "todo file missing",
+            SyncStatus::Detached => "detached",
+            SyncStatus::Stale => "out of date",
+            SyncStatus::Conflicted => "conflicted",
+        })
+    }
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SyncMode::Hardlink => "hard link",
+            SyncMode::Symlink => "symlink",
+            SyncMode::Copy => "copy",
+            SyncMode::Merge => "merge",
+        })
+    }
+}
+
+pub fn load_sync_records() -> AnyResult<Vec<SyncRecord>> {
+    let syncs_path = get_syncs_path()?;
+    match fs::read_to_string(&syncs_path) {
+        Ok(content) => Ok(toml::from_str::<SyncRecords>(&content)
+            .map_err(|e| TodoError::ConfigInvalid {
+                path: syncs_path,
+                reason: e.message().to_string(),
+            })?
+            .syncs),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err)
+            .with_context(|| format!("Failed to read sync records at {}", syncs_path.display())),
+    }
+}
+
+pub fn save_sync_records(syncs: Vec<SyncRecord>) -> AnyResult<()> {
+    let syncs_path = get_syncs_path()?;
+    fs::create_dir_all(syncs_path.parent().unwrap()).context("Failed to create todo directory")?;
+    fs::write(&syncs_path, toml::to_string(&SyncRecords { syncs })?)
+        .context("Failed to write sync records")
+}
+
+/// Finds how `original` is synced, falling back to a hard link with the same name in
+/// the current directory for syncs made before they were recorded. A file that merely
+/// has the same name isn't one.
+fn find_record(original: &Path) -> AnyResult<Option<SyncRecord>> {
+    if let Some(record) = load_sync_records()?
+        .into_iter()
+        .find(|record| record.source == original)
+    {
+        return Ok(Some(record));
+    }
+    let target = env::current_dir()?.join(default_target(original)?);
+    Ok(same_file(original, &target).then(|| SyncRecord {
+        source: original.to_path_buf(),
+        target,
+        mode: SyncMode::Hardlink,
+    }))
+}
+
+fn default_target(original: &Path) -> AnyResult<PathBuf> {
+    original
         .file_name()
-        .ok_or_else(|| anyhow!("Invalid path"))?;
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow!("Invalid path"))
+}
+
+pub fn sync(original: PathBuf, mode: SyncMode, target: Option<PathBuf>) -> AnyResult<()> {
     if !original.exists() {
-        // if !fs::exists(&original)? {
-        return Err(anyhow!("❌ there's no todo file for the current directory"));
+        return Err(TodoError::NoTodoFile.into());
     }
-    // if fs::exists(link)? {
-    if PathBuf::from(link).exists() {
-        println!("⚠️ {link:?} is already synced with {original:?}");
+    if let Some(record) = find_record(&original)? {
+        println!(
+            "⚠️ {:?} is already synced with {original:?} ({})",
+            record.target, record.mode
+        );
         return Ok(());
     }
-    fs::hard_link(&original, link).map_err(|e| anyhow!("Error: {e}"))
+    let target = env::current_dir()?.join(match target {
+        Some(target) => target,
+        None => default_target(&original)?,
+    });
+    // An existing file can only be merged with, which lets a committed todo file be
+    // shared with the todo file in the store.
+    let adopt = mode == SyncMode::Merge && target.exists();
+    if target.exists() && !adopt {
+        return Err(anyhow!("{target:?} already exists"));
+    }
+    if !adopt {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).with_context(|| format!("Failed to create {parent:?}"))?;
+        }
+        link(mode, &original, &target).with_context(|| format!("Failed to sync {target:?}"))?;
+    }
+    let record = SyncRecord {
+        source: original,
+        target,
+        mode,
+    };
+    if adopt {
+        // Both files get the lines only one of them has, which becomes the base of the
+        // next merges
+        merge_into(&record.target, &record.source)?;
+        let merged = fs::read_to_string(&record.source).context("Failed to read todo file")?;
+        fs::write(&record.target, &merged)
+            .with_context(|| format!("Failed to write {:?}", record.target))?;
+        save_base(&record.source, &merged)?;
+        println!(
+            "ℹ Merged the todos of {:?} into the todo file",
+            record.target
+        );
+    } else if mode == SyncMode::Merge {
+        save_base(
+            &record.source,
+            &fs::read_to_string(&record.source).context("Failed to read todo file")?,
+        )?;
+    }
+
+    let mut syncs = load_sync_records()?;
+    syncs.push(record);
+    save_sync_records(syncs)
 }
 
 pub fn unsync(original: PathBuf) -> AnyResult<()> {
-    let synced_path = original
-        .file_name()
-        .ok_or_else(|| anyhow!("Invalid path"))?;
-    if !PathBuf::from(synced_path).exists() {
-        // if !fs::exists(synced_path)? {
+    let Some(record) = find_record(&original)? else {
         println!("⚠️ {original:?} is not synced");
         return Ok(());
+    };
+    if record.mode == SyncMode::Merge {
+        // The merged file is usually shared with others, so it stays and is just no
+        // longer merged with.
+        match fs::remove_file(base_path(&original)?) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e).context("Failed to remove the sync base")
+            }
+            _ => {}
+        }
+    } else {
+        match sync_status(&record) {
+            SyncStatus::Detached | SyncStatus::Stale | SyncStatus::Conflicted => println!(
+                "⚠️ {:?} differs from the todo file, keeping it in place",
+                record.target
+            ),
+            SyncStatus::Missing => {}
+            SyncStatus::Intact | SyncStatus::SourceMissing => fs::remove_file(&record.target)
+                .with_context(|| format!("Failed to remove {:?}", record.target))?,
+        }
+    }
+    let mut syncs = load_sync_records()?;
+    syncs.retain(|r| r.source != original);
+    save_sync_records(syncs)
+}
+
+pub fn status(original: PathBuf) -> AnyResult<()> {
+    let Some(record) = find_record(&original)? else {
+        println!("ℹ {original:?} is not synced");
+        return Ok(());
+    };
+    let status = sync_status(&record);
+    let icon = if status == SyncStatus::Intact {
+        "✅"
+    } else {
+        "⚠️"
+    };
+    println!("{icon} {:?} ({}): {status}", record.target, record.mode);
+    match status {
+        SyncStatus::Intact | SyncStatus::SourceMissing => {}
+        SyncStatus::Conflicted => {
+            println!("Resolve the conflict markers in the todo file or the synced file")
+        }
+        _ => println!("Run `todo sync repair` to fix it"),
+    }
+    Ok(())
+}
+
+pub fn repair(original: PathBuf) -> AnyResult<()> {
+    let Some(record) = find_record(&original)? else {
+        println!("⚠️ {original:?} is not synced");
+        return Ok(());
+    };
+    match repair_record(&record)? {
+        SyncStatus::Intact => println!("✅ {:?} is already intact", record.target),
+        _ => println!("✅ Repaired {:?} ({})", record.target, record.mode),
+    }
+    Ok(())
+}
+
+/// Relinks `record.target` to its todo file. If the synced file was edited after it got
+/// detached from the todo file, its content is kept by copying it into the store first.
+/// Returns the status the record had before repairing it.
+pub fn repair_record(record: &SyncRecord) -> AnyResult<SyncStatus> {
+    let status = sync_status(record);
+    match status {
+        SyncStatus::Intact => return Ok(status),
+        SyncStatus::Conflicted => {
+            return Err(anyhow!(
+                "{:?} has unresolved conflict markers, resolve them first",
+                record.target
+            ))
+        }
+        SyncStatus::Stale if record.mode == SyncMode::Merge => {
+            merge_record(record)?;
+            return Ok(status);
+        }
+        SyncStatus::SourceMissing if !record.target.exists() => {
+            return Err(anyhow!(
+                "Both {:?} and {:?} are missing",
+                record.source,
+                record.target
+            ));
+        }
+        SyncStatus::SourceMissing => {
+            fs::create_dir_all(record.source.parent().unwrap())?;
+            fs::copy(&record.target, &record.source)?;
+            println!("ℹ Restored the todo file from {:?}", record.target);
+        }
+        SyncStatus::Detached | SyncStatus::Stale if is_newer(&record.target, &record.source) => {
+            if !same_content(&record.source, &record.target) {
+                let id = trash(&record.source)?;
+                println!(
+                    "ℹ Kept the newer content of {:?}, the todo file was moved to the trash as {id}",
+                    record.target
+                );
+            }
+            fs::copy(&record.target, &record.source)?;
+        }
+        _ => {}
+    }
+    // The synced file is replaced by a new link, keeping what only it had in the trash
+    if let Ok(metadata) = record.target.symlink_metadata() {
+        if metadata.is_file() && !same_content(&record.source, &record.target) {
+            let id = trash(&record.target)?;
+            println!(
+                "ℹ Moved the older content of {:?} to the trash as {id}",
+                record.target
+            );
+        } else {
+            fs::remove_file(&record.target)
+                .with_context(|| format!("Failed to remove {:?}", record.target))?;
+        }
+    } else if let Some(parent) = record.target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    link(record.mode, &record.source, &record.target)
+        .with_context(|| format!("Failed to sync {:?}", record.target))?;
+    if record.mode == SyncMode::Merge {
+        save_base(&record.source, &fs::read_to_string(&record.source)?)?;
+    }
+    Ok(status)
+}
+
+/// Points the syncs of the todo files that moved from `from_store` to `to_store` at
+/// their new location, moves synced files under `from` to `to` along with the project,
+/// and relinks them. Returns how many syncs were updated.
+pub fn relocate_syncs(
+    from_store: &Path,
+    to_store: &Path,
+    from: &Path,
+    to: &Path,
+) -> AnyResult<usize> {
+    let mut syncs = load_sync_records()?;
+    let mut relocated = Vec::new();
+    for record in &mut syncs {
+        let Ok(relative) = record.source.strip_prefix(from_store) else {
+            continue;
+        };
+        let old_base = base_path(&record.source)?;
+        record.source = to_store.join(relative);
+        if let Ok(relative) = record.target.strip_prefix(from) {
+            record.target = to.join(relative);
+        }
+        if old_base.exists() {
+            let new_base = base_path(&record.source)?;
+            fs::create_dir_all(new_base.parent().unwrap())?;
+            fs::rename(old_base, new_base)?;
+        }
+        relocated.push(record.clone());
+    }
+    if relocated.is_empty() {
+        return Ok(0);
+    }
+    save_sync_records(syncs)?;
+    for record in &relocated {
+        repair_record(record)?;
+    }
+    Ok(relocated.len())
+}
+
+/// Merges the todo file of the current directory with its synced copy, if it's synced
+/// in merge mode. With `remind`, warns when an earlier conflict is still unresolved.
+pub fn merge_synced(original: &Path, remind: bool) -> AnyResult<()> {
+    let record = load_sync_records()?
+        .into_iter()
+        .find(|record| record.source == original && record.mode == SyncMode::Merge);
+    match record {
+        Some(record) => {
+            let conflicted = merge_record(&record)?;
+            // The conflict is reported by the merge that caused it and, with `remind`,
+            // by later merges until it's resolved
+            if remind && !conflicted && sync_status(&record) == SyncStatus::Conflicted {
+                eprintln!(
+                    "⚠️ The todo file and {:?} still have unresolved conflict markers",
+                    record.target
+                );
+            }
+            Ok(())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Three-way merges the todo file and its synced copy, using the content they had after
+/// the last merge as the common ancestor, and writes the result to both. Conflicting
+/// changes are kept side by side between conflict markers so neither side is lost.
+/// Returns whether there were conflicts.
+pub fn merge_record(record: &SyncRecord) -> AnyResult<bool> {
+    let (Ok(ours), Ok(theirs)) = (
+        fs::read_to_string(&record.source),
+        fs::read_to_string(&record.target),
+    ) else {
+        return Ok(false);
+    };
+    let base_path = base_path(&record.source)?;
+    let base = fs::read_to_string(&base_path).unwrap_or_default();
+    if ours == theirs {
+        if base != ours {
+            save_base(&record.source, &ours)?;
+        }
+        return Ok(false);
+    }
+    let (merged, conflicted) = match MergeOptions::new()
+        .set_conflict_style(ConflictStyle::Merge)
+        .merge(&base, &ours, &theirs)
+    {
+        Ok(merged) => (merged, false),
+        Err(merged) => (merged, true),
+    };
+    if merged != ours {
+        fs::write(&record.source, &merged).context("Failed to write todo file")?;
+    }
+    if merged != theirs {
+        fs::write(&record.target, &merged)
+            .with_context(|| format!("Failed to write {:?}", record.target))?;
+    }
+    save_base(&record.source, &merged)?;
+    if conflicted {
+        eprintln!(
+            "⚠️ Conflicting changes between the todo file (ours) and {:?} (theirs), resolve the conflict markers in either of them",
+            record.target
+        );
+    }
+    Ok(conflicted)
+}
+
+/// Where the last merged content of a todo file synced in merge mode is kept.
+fn base_path(source: &Path) -> AnyResult<PathBuf> {
+    let todo_path = get_todo_path()?;
+    let relative = match source.strip_prefix(&todo_path) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => source
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect(),
+    };
+    Ok(get_sync_bases_path()?.join(relative))
+}
+
+fn save_base(source: &Path, content: &str) -> AnyResult<()> {
+    let base_path = base_path(source)?;
+    fs::create_dir_all(base_path.parent().unwrap())
+        .with_context(|| format!("Failed to create {:?}", base_path.parent()))?;
+    fs::write(&base_path, content).context("Failed to save sync base")
+}
+
+pub fn sync_status(record: &SyncRecord) -> SyncStatus {
+    if !record.source.exists() {
+        return SyncStatus::SourceMissing;
+    }
+    let Ok(metadata) = record.target.symlink_metadata() else {
+        return SyncStatus::Missing;
+    };
+    if record.mode == SyncMode::Merge
+        && [&record.source, &record.target].iter().any(|path| {
+            fs::read_to_string(path).is_ok_and(|content| has_conflict_markers(&content))
+        })
+    {
+        return SyncStatus::Conflicted;
+    }
+    match record.mode {
+        SyncMode::Hardlink if same_file(&record.source, &record.target) => SyncStatus::Intact,
+        SyncMode::Symlink
+            if metadata.file_type().is_symlink()
+                && fs::read_link(&record.target).is_ok_and(|dest| dest == record.source) =>
+        {
+            SyncStatus::Intact
+        }
+        SyncMode::Copy | SyncMode::Merge if same_content(&record.source, &record.target) => {
+            SyncStatus::Intact
+        }
+        SyncMode::Copy | SyncMode::Merge => SyncStatus::Stale,
+        _ => SyncStatus::Detached,
+    }
+}
+
+/// Whether `content` has the markers a conflicting merge leaves around both sides
+fn has_conflict_markers(content: &str) -> bool {
+    let mut lines = content.lines();
+    lines.any(|line| line.starts_with("<<<<<<<"))
+        && lines.any(|line| line.starts_with("======="))
+        && lines.any(|line| line.starts_with(">>>>>>>"))
+}
+
+fn link(mode: SyncMode, source: &Path, target: &Path) -> io::Result<()> {
+    match mode {
+        SyncMode::Hardlink => fs::hard_link(source, target),
+        SyncMode::Symlink => symlink(source, target),
+        SyncMode::Copy | SyncMode::Merge => fs::copy(source, target).map(|_| ()),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, target)
+}
+
+#[cfg(windows)]
+fn symlink(source: &Path, target: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(source, target)
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (fs::metadata(a), fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Path, b: &Path) -> bool {
+    same_content(a, b)
+}
+
+fn same_content(a: &Path, b: &Path) -> bool {
+    match (fs::read(a), fs::read(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+fn is_newer(a: &Path, b: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(a), modified(b)) {
+        (Ok(a), Ok(b)) => a > b,
+        _ => false,
     }
-    fs::remove_file(synced_path).map_err(|e| anyhow!("Error: {e}"))
 }
@@ -0,0 +1,118 @@
+use crate::{
+    editor::open_editor,
+    utils::{find_local_project, get_templates_path, get_todo_file_path},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use chrono::Local;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+/// The template used for new todo files when none is asked for
+const DEFAULT_TEMPLATE: &str = "default";
+
+/// Creates the todo file for the current directory from a template and opens it
+pub fn new(template: Option<String>) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    if path.exists() {
+        return Err(anyhow!("{} already exists", path.display()));
+    }
+    create_todo_file(&path, template.as_deref())?;
+    println!("✅ Created {}", path.display());
+    open_editor(&path, None)
+}
+
+/// Writes a new todo file at `path` from the template `name`, or from the `default`
+/// template when there is one, or empty.
+pub fn create_todo_file(path: &Path, name: Option<&str>) -> AnyResult<()> {
+    let content = match name {
+        Some(name) => match find_template(name)? {
+            Some(template) => render(&template)?,
+            None => return Err(no_such_template(name)?),
+        },
+        None => match find_template(DEFAULT_TEMPLATE)? {
+            Some(template) => render(&template)?,
+            None => String::new(),
+        },
+    };
+    fs::create_dir_all(path.parent().unwrap()).context("Failed to create todo directory")?;
+    fs::write(path, content).context("Failed to create todo file")
+}
+
+/// The directories templates are looked up in, closest first: `.todo/templates` in the
+/// current directory and its ancestors, then `~/.todo/templates`
+fn template_dirs() -> AnyResult<Vec<PathBuf>> {
+    let mut dirs: Vec<PathBuf> = env::current_dir()?
+        .ancestors()
+        .map(|dir| dir.join(".todo").join("templates"))
+        .filter(|dir| dir.is_dir())
+        .collect();
+    let global = get_templates_path()?;
+    if !dirs.contains(&global) {
+        dirs.push(global);
+    }
+    Ok(dirs)
+}
+
+/// Finds the template called `name`, whatever its extension
+fn find_template(name: &str) -> AnyResult<Option<PathBuf>> {
+    for dir in template_dirs()? {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_file() && path.file_stem().is_some_and(|stem| stem == name) {
+                return Ok(Some(path));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn no_such_template(name: &str) -> AnyResult<anyhow::Error> {
+    let mut names = Vec::new();
+    for dir in template_dirs()? {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if let Some(stem) = path.file_stem().filter(|_| path.is_file()) {
+                names.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    Ok(if names.is_empty() {
+        anyhow!(
+            "No template named `{name}`, add one to {}",
+            get_templates_path()?.display()
+        )
+    } else {
+        anyhow!(
+            "No template named `{name}`, the templates are: {}",
+            names.join(", ")
+        )
+    })
+}
+
+/// The content of a template with `{project}` replaced by the name of the project and
+/// `{date}` by today's date
+fn render(template: &Path) -> AnyResult<String> {
+    let content = fs::read_to_string(template)
+        .with_context(|| format!("Failed to read template {}", template.display()))?;
+    let project_dir = match find_local_project()? {
+        Some((dir, _)) => dir,
+        None => env::current_dir()?,
+    };
+    let project = project_dir
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    Ok(content
+        .replace("{project}", &project)
+        .replace("{date}", &Local::now().format("%Y-%m-%d").to_string()))
+}
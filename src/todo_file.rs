@@ -0,0 +1,584 @@
+use crate::{
+    attributes::id,
+    error::TodoError,
+    state::{states, State},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use regex::Regex;
+use std::{collections::HashSet, fs, io, ops::Range, path::Path};
+
+const BOM: char = '\u{feff}';
+
+/// Matches a markdown heading, capturing its level and its title
+pub const HEADING_PATTERN: &str = r"^(#{1,6})\s+(.+?)\s*#*\s*$";
+
+/// Matches a fence opening or closing a code block, capturing its backticks or tildes
+const FENCE_PATTERN: &str = r"^(?:\s*>)*\s*(`{3,}|~{3,})";
+
+/// Matches an ordered list item, capturing what comes before its number, the number and
+/// its delimiter
+const ORDERED_PATTERN: &str = r"^((?:\s*>)*\s*)(\d{1,9})([.)])(?:\s|$)";
+
+/// A todo file split into lines, remembering the byte order mark, the line ending of
+/// every line and whether the file ended with a newline, so that rewriting it leaves
+/// untouched lines exactly as they were.
+#[derive(Debug, Clone, Default)]
+pub struct TodoFile {
+    bom: bool,
+    lines: Vec<Line>,
+    trailing_newline: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Line {
+    text: String,
+    ending: &'static str,
+}
+
+impl TodoFile {
+    pub fn read(path: &Path) -> AnyResult<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                Err(TodoError::NoTodoFile.into())
+            }
+            Err(error) => Err(error).context("Failed to read todo file"),
+        }
+    }
+
+    pub fn parse(content: &str) -> Self {
+        let (bom, content) = match content.strip_prefix(BOM) {
+            Some(rest) => (true, rest),
+            None => (false, content),
+        };
+        let lines = content
+            .split_inclusive('\n')
+            .map(|line| {
+                if let Some(text) = line.strip_suffix("\r\n") {
+                    Line {
+                        text: text.to_string(),
+                        ending: "\r\n",
+                    }
+                } else if let Some(text) = line.strip_suffix('\n') {
+                    Line {
+                        text: text.to_string(),
+                        ending: "\n",
+                    }
+                } else {
+                    Line {
+                        text: line.to_string(),
+                        ending: "",
+                    }
+                }
+            })
+            .collect();
+        Self {
+            bom,
+            lines,
+            trailing_newline: content.ends_with('\n'),
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> AnyResult<()> {
+        fs::write(path, self.to_string()).context("Failed to write todo file")
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().map(|line| line.text.as_str())
+    }
+
+    pub fn line(&self, index: usize) -> Option<&str> {
+        self.lines.get(index).map(|line| line.text.as_str())
+    }
+
+    /// The lines outside fenced code blocks, with their index. Checkboxes in code blocks
+    /// aren't todos.
+    pub fn lines_outside_code(&self) -> impl Iterator<Item = (usize, &str)> {
+        let code = self.code_lines();
+        self.lines().enumerate().filter(move |(i, _)| !code[*i])
+    }
+
+    /// Whether each line is part of a fenced code block, fences included
+    fn code_lines(&self) -> Vec<bool> {
+        let fence = Regex::new(FENCE_PATTERN).unwrap();
+        let mut opening: Option<String> = None;
+        self.lines()
+            .map(|line| {
+                let caps = fence.captures(line);
+                match (&opening, caps) {
+                    (None, Some(caps)) => opening = Some(caps[1].to_string()),
+                    // A closing fence is at least as long as the opening one, made of the
+                    // same character and followed by nothing
+                    (Some(open), Some(caps))
+                        if caps[1].starts_with(&open[..1])
+                            && caps[1].len() >= open.len()
+                            && line[caps.get(0).unwrap().end()..].trim().is_empty() =>
+                    {
+                        opening = None;
+                        return true;
+                    }
+                    _ => {}
+                }
+                opening.is_some()
+            })
+            .collect()
+    }
+
+    /// The indices of the lines holding todos, in order. The number of a todo, as shown by
+    /// `todo list`, is its position here plus one.
+    pub fn todo_lines(&self) -> Vec<usize> {
+        let regex = states().regex();
+        self.lines_outside_code()
+            .filter(|(_, line)| regex.is_match(line))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// The IDs of the unchecked todos, which block the todos naming them in `blocked-by:`
+    pub fn open_ids(&self) -> HashSet<String> {
+        let regex = states().regex();
+        self.lines_outside_code()
+            .filter_map(|(_, line)| regex.captures(line))
+            .filter(|caps| states().state(&caps[1]).is_some_and(State::is_open))
+            .filter_map(|caps| id(&caps[2]).map(String::from))
+            .collect()
+    }
+
+    /// Replaces the text of the line at `index`, keeping its line ending.
+    pub fn set_line(&mut self, index: usize, text: String) {
+        if let Some(line) = self.lines.get_mut(index) {
+            line.text = text;
+        }
+    }
+
+    /// Appends a line using the file's line ending.
+    pub fn push_line(&mut self, text: String) {
+        if self.lines.is_empty() {
+            self.trailing_newline = true;
+        }
+        self.lines.push(Line { text, ending: "" });
+    }
+
+    /// Inserts a line before the line at `index`, using the file's line ending.
+    pub fn insert_line(&mut self, index: usize, text: String) {
+        if index >= self.lines.len() {
+            return self.push_line(text);
+        }
+        self.lines.insert(index, Line { text, ending: "" });
+    }
+
+    /// The lines under the heading titled `title`, ignoring case, up to the next heading
+    /// of the same or a higher level. The heading itself isn't included.
+    pub fn section(&self, title: &str) -> Option<Range<usize>> {
+        let headings = self.headings();
+        let k = headings
+            .iter()
+            .position(|(_, _, other)| other.eq_ignore_ascii_case(title.trim()))?;
+        let (index, level, _) = headings[k];
+        let end = headings[k + 1..]
+            .iter()
+            .find(|(_, other, _)| *other <= level)
+            .map_or(self.lines.len(), |(i, _, _)| *i);
+        Some(index + 1..end)
+    }
+
+    /// The index, level and title of every heading outside fenced code blocks, where
+    /// lines like `# comment` aren't headings
+    fn headings(&self) -> Vec<(usize, usize, &str)> {
+        let regex = Regex::new(HEADING_PATTERN).unwrap();
+        self.lines_outside_code()
+            .filter_map(|(i, line)| {
+                let caps = regex.captures(line)?;
+                Some((i, caps[1].len(), caps.get(2)?.as_str()))
+            })
+            .collect()
+    }
+
+    /// The lines of the section titled `title`, or all lines when there's no title.
+    pub fn scope(&self, title: Option<&str>) -> AnyResult<Range<usize>> {
+        match title {
+            Some(title) => self
+                .section(title)
+                .ok_or_else(|| anyhow!("No section titled `{title}`")),
+            None => Ok(0..self.lines.len()),
+        }
+    }
+
+    /// Inserts lines at the end of the section titled `title`, before its subsections and
+    /// the blank lines separating it from the next one. A missing section is added at the
+    /// end of the file, in which case `true` is returned.
+    pub fn insert_into_section(&mut self, title: &str, lines: Vec<String>) -> bool {
+        let (mut index, created) = match self.section(title) {
+            Some(range) => {
+                let mut end = self
+                    .headings()
+                    .into_iter()
+                    .map(|(i, _, _)| i)
+                    .find(|i| range.contains(i))
+                    .unwrap_or(range.end);
+                while end > range.start
+                    && self
+                        .line(end - 1)
+                        .is_some_and(|line| line.trim().is_empty())
+                {
+                    end -= 1;
+                }
+                (end, false)
+            }
+            None => {
+                if self
+                    .lines()
+                    .last()
+                    .is_some_and(|line| !line.trim().is_empty())
+                {
+                    self.push_line(String::new());
+                }
+                self.push_line(format!("## {}", title.trim()));
+                (self.lines.len(), true)
+            }
+        };
+        for line in lines {
+            self.insert_line(index, line);
+            index += 1;
+        }
+        created
+    }
+
+    /// The line at `index` and the lines after it that are indented further, i.e. a todo
+    /// and its subtasks. Blank lines between them are part of it, the ones after them
+    /// aren't.
+    pub fn item(&self, index: usize) -> Range<usize> {
+        let Some(parent) = self.line(index) else {
+            return index..index;
+        };
+        let parent_indent = indent(parent);
+        let mut end = index + 1;
+        for (i, line) in self.lines().enumerate().skip(index + 1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if indent(line) <= parent_indent {
+                break;
+            }
+            end = i + 1;
+        }
+        index..end
+    }
+
+    /// The todos at the same level as the one at `index`, with their subtasks: the other
+    /// subtasks of its parent, or the other todos between the same headings.
+    pub fn siblings(&self, index: usize) -> Vec<Range<usize>> {
+        let headings: Vec<usize> = self.headings().into_iter().map(|(i, _, _)| i).collect();
+        let todos: HashSet<usize> = self.todo_lines().into_iter().collect();
+        let line = |i: usize| self.line(i).unwrap_or_default();
+        let level = indent(line(index));
+
+        let previous_heading = headings.iter().rev().copied().find(|&i| i < index);
+        let parent = (previous_heading.map_or(0, |i| i + 1)..index)
+            .rev()
+            .find(|&i| todos.contains(&i) && indent(line(i)) < level);
+        let region = match parent {
+            Some(parent) => parent + 1..self.item(parent).end,
+            None => {
+                let start = previous_heading.map_or(0, |i| i + 1);
+                let end = headings
+                    .iter()
+                    .copied()
+                    .find(|&i| i >= index)
+                    .unwrap_or(self.lines.len());
+                start..end
+            }
+        };
+        region
+            .filter(|&i| todos.contains(&i) && indent(line(i)) == level)
+            .map(|i| self.item(i))
+            .collect()
+    }
+
+    /// Puts the lines of `slots[order[k]]` where the lines of `slots[k]` were, leaving the
+    /// lines outside of the slots in place, and renumbers the ordered lists they were part
+    /// of. The slots must be sorted and not overlap.
+    pub fn reorder(&mut self, slots: &[Range<usize>], order: &[usize]) {
+        let mut lines = Vec::with_capacity(self.lines.len());
+        let mut i = 0;
+        for (slot, &from) in slots.iter().zip(order) {
+            lines.extend_from_slice(&self.lines[i..slot.start]);
+            lines.extend_from_slice(&self.lines[slots[from].clone()]);
+            i = slot.end;
+        }
+        lines.extend_from_slice(&self.lines[i..]);
+        self.lines = lines;
+        if let (Some(first), Some(last)) = (slots.first(), slots.last()) {
+            let moved: Vec<usize> = (first.start..last.end).collect();
+            self.renumber(&moved, &[]);
+        }
+    }
+
+    /// Removes the lines whose indices are in `indices`, renumbering the ordered lists
+    /// they were part of.
+    pub fn remove_lines(&mut self, indices: &[usize]) {
+        self.renumber(indices, indices);
+        let mut index = 0;
+        self.lines.retain(|_| {
+            let keep = !indices.contains(&index);
+            index += 1;
+            keep
+        });
+    }
+
+    /// The ordered lists, each as the indices of its items. Nested lists are lists of
+    /// their own.
+    fn ordered_lists(&self) -> Vec<Vec<usize>> {
+        let item = Regex::new(ORDERED_PATTERN).unwrap();
+        let mut lists: Vec<Vec<usize>> = Vec::new();
+        // The lists still going on, outermost first: their indent, their delimiter and
+        // where they are in `lists`
+        let mut open: Vec<(usize, String, usize)> = Vec::new();
+        for (i, line) in self.lines_outside_code() {
+            // Blank lines don't end lists
+            if line
+                .trim_start_matches(|c: char| c.is_whitespace() || c == '>')
+                .is_empty()
+            {
+                continue;
+            }
+            let Some(caps) = item.captures(line) else {
+                open.retain(|(level, _, _)| *level < indent(line));
+                continue;
+            };
+            let level = caps[1].len();
+            open.retain(|(other, _, _)| *other <= level);
+            match open.last() {
+                Some((other, delimiter, list)) if *other == level && *delimiter == caps[3] => {
+                    lists[*list].push(i)
+                }
+                _ => {
+                    open.retain(|(other, _, _)| *other < level);
+                    open.push((level, caps[3].to_string(), lists.len()));
+                    lists.push(vec![i]);
+                }
+            }
+        }
+        lists
+    }
+
+    /// Renumbers the ordered lists with items at `changed`, leaving out the items at
+    /// `removed`, from their lowest number. Lists numbering every item the same, like
+    /// `1.`, are left alone.
+    fn renumber(&mut self, changed: &[usize], removed: &[usize]) {
+        let item = Regex::new(ORDERED_PATTERN).unwrap();
+        for list in self.ordered_lists() {
+            if !list.iter().any(|i| changed.contains(i)) {
+                continue;
+            }
+            let numbers: Vec<u32> = list
+                .iter()
+                .filter_map(|&i| item.captures(self.line(i)?)?[2].parse().ok())
+                .collect();
+            if numbers.windows(2).all(|pair| pair[0] == pair[1]) {
+                continue;
+            }
+            let mut number = numbers.iter().copied().min().unwrap_or(1);
+            for &i in list.iter().filter(|i| !removed.contains(i)) {
+                let line = self.line(i).unwrap_or_default();
+                let Some(digits) = item.captures(line).and_then(|caps| caps.get(2)) else {
+                    continue;
+                };
+                let line = format!(
+                    "{}{number}{}",
+                    &line[..digits.start()],
+                    &line[digits.end()..]
+                );
+                self.set_line(i, line);
+                number += 1;
+            }
+        }
+    }
+
+    /// The line ending used by most lines, for lines that don't have one of their own.
+    fn newline(&self) -> &'static str {
+        let crlf = self.lines.iter().filter(|l| l.ending == "\r\n").count();
+        let lf = self.lines.iter().filter(|l| l.ending == "\n").count();
+        if crlf > lf {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+}
+
+impl std::fmt::Display for TodoFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.bom {
+            write!(f, "{BOM}")?;
+        }
+        let newline = self.newline();
+        let last = self.lines.len().saturating_sub(1);
+        for (i, line) in self.lines.iter().enumerate() {
+            f.write_str(&line.text)?;
+            let ending = match (i == last, line.ending) {
+                (true, _) if !self.trailing_newline => "",
+                (_, "") => newline,
+                (_, ending) => ending,
+            };
+            f.write_str(ending)?;
+        }
+        Ok(())
+    }
+}
+
+/// How deep a line is nested: its leading whitespace, counting blockquote markers
+fn indent(line: &str) -> usize {
+    line.len()
+        - line
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '>')
+            .len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(lines: &[&str]) -> TodoFile {
+        TodoFile::parse(&(lines.join("\n") + "\n"))
+    }
+
+    #[test]
+    fn crlf_file_round_trips() {
+        let content = "# Todo\r\n\r\n- [ ] a\r\n- [ ] b\r\n";
+        let mut file = TodoFile::parse(content);
+        assert_eq!(file.to_string(), content);
+        file.remove_lines(&[2]);
+        assert_eq!(file.to_string(), "# Todo\r\n\r\n- [ ] b\r\n");
+        file.push_line("- [ ] c".to_string());
+        assert_eq!(file.to_string(), "# Todo\r\n\r\n- [ ] b\r\n- [ ] c\r\n");
+    }
+
+    #[test]
+    fn bom_is_kept() {
+        let content = "\u{feff}# Todo\n- [ ] a\n";
+        let mut file = TodoFile::parse(content);
+        assert_eq!(file.line(0), Some("# Todo"));
+        assert_eq!(file.to_string(), content);
+        file.push_line("- [ ] b".to_string());
+        assert_eq!(file.to_string(), "\u{feff}# Todo\n- [ ] a\n- [ ] b\n");
+        file.remove_lines(&[0]);
+        assert_eq!(file.to_string(), "\u{feff}- [ ] a\n- [ ] b\n");
+    }
+
+    #[test]
+    fn missing_final_newline_stays_missing() {
+        let content = "# Todo\n- [ ] a";
+        let mut file = TodoFile::parse(content);
+        assert_eq!(file.to_string(), content);
+        file.push_line("- [ ] b".to_string());
+        assert_eq!(file.to_string(), "# Todo\n- [ ] a\n- [ ] b");
+        file.remove_lines(&[2]);
+        assert_eq!(file.to_string(), content);
+    }
+
+    #[test]
+    fn mixed_line_endings_are_kept_per_line() {
+        let content = "# Todo\r\n- [ ] a\n- [ ] b\r\n- [ ] c\r\n";
+        let mut file = TodoFile::parse(content);
+        assert_eq!(file.to_string(), content);
+        file.remove_lines(&[2]);
+        assert_eq!(file.to_string(), "# Todo\r\n- [ ] a\n- [ ] c\r\n");
+        file.push_line("- [ ] d".to_string());
+        assert_eq!(
+            file.to_string(),
+            "# Todo\r\n- [ ] a\n- [ ] c\r\n- [ ] d\r\n"
+        );
+    }
+
+    #[test]
+    fn item_keeps_subtasks_after_blank_lines() {
+        let file = file(&["- [ ] b", "", "  - [ ] b-child", "", "- [ ] a"]);
+        assert_eq!(file.item(0), 0..3);
+        assert_eq!(file.item(2), 2..3);
+        assert_eq!(file.item(4), 4..5);
+    }
+
+    #[test]
+    fn item_ends_at_same_indentation() {
+        let file = file(&[
+            "- [ ] a",
+            "  - [ ] a1",
+            "    - [ ] a1x",
+            "  - [ ] a2",
+            "- [ ] b",
+        ]);
+        assert_eq!(file.item(0), 0..4);
+        assert_eq!(file.item(1), 1..3);
+        assert_eq!(file.item(3), 3..4);
+    }
+
+    #[test]
+    fn siblings_of_subtask_after_blank_line() {
+        let file = file(&["- [ ] parent", "", "  - [ ] child", "  - [ ] other"]);
+        assert_eq!(file.siblings(2), vec![2..3, 3..4]);
+        assert_eq!(file.siblings(0), vec![0..4]);
+    }
+
+    #[test]
+    fn siblings_stop_at_headings() {
+        let file = file(&["# A", "- [ ] a1", "- [ ] a2", "# B", "- [ ] b1"]);
+        assert_eq!(file.siblings(1), vec![1..2, 2..3]);
+        assert_eq!(file.siblings(4), vec![4..5]);
+    }
+
+    #[test]
+    fn headings_in_code_blocks_dont_split_sections() {
+        let mut file = file(&[
+            "# Setup",
+            "```sh",
+            "# install deps",
+            "```",
+            "- [ ] setup",
+            "# Other",
+        ]);
+        assert_eq!(file.section("Setup"), Some(1..5));
+        assert_eq!(file.section("install deps"), None);
+        file.insert_into_section("Setup", vec!["- [ ] new".to_string()]);
+        assert_eq!(file.line(5), Some("- [ ] new"));
+    }
+
+    #[test]
+    fn reorder_moves_subtasks_with_parents() {
+        let mut file = file(&["- [ ] b", "", "  - [ ] b-child", "- [ ] a"]);
+        let siblings = file.siblings(0);
+        assert_eq!(siblings, vec![0..3, 3..4]);
+        file.reorder(&siblings, &[1, 0]);
+        assert_eq!(
+            file.lines().collect::<Vec<_>>(),
+            ["- [ ] a", "- [ ] b", "", "  - [ ] b-child"]
+        );
+    }
+
+    #[test]
+    fn reorder_and_remove_renumber_ordered_lists() {
+        let mut file = file(&[
+            "3. three", "4. four", "5. five", "", "Lazy:", "1. one", "1. one",
+        ]);
+        file.reorder(&[1..2, 2..3], &[1, 0]);
+        assert_eq!(
+            file.lines().collect::<Vec<_>>(),
+            ["3. three", "4. five", "5. four", "", "Lazy:", "1. one", "1. one"]
+        );
+        file.remove_lines(&[0, 5]);
+        assert_eq!(
+            file.lines().collect::<Vec<_>>(),
+            ["3. five", "4. four", "", "Lazy:", "1. one"]
+        );
+    }
+
+    #[test]
+    fn reorder_leaves_lines_between_slots() {
+        let mut file = file(&["- [ ] b", "note", "- [ ] a"]);
+        file.reorder(&[0..1, 2..3], &[1, 0]);
+        assert_eq!(
+            file.lines().collect::<Vec<_>>(),
+            ["- [ ] a", "note", "- [ ] b"]
+        );
+    }
+}
@@ -1,5 +1,18 @@
-use crate::utils::{get_todo_file_path, highlight_indices};
-use anyhow::Result as AnyResult;
+use crate::{
+    attributes::{blockers, due, id, priority},
+    cli::{Position, SelectArgs, SortKey},
+    editor::open_editor,
+    error::TodoError,
+    state::{states, State},
+    template::create_todo_file,
+    todo_file::TodoFile,
+    utils::{
+        can_prompt, collect_files, ensure_can_prompt, get_original_dir, get_todo_file_path,
+        get_todo_path, highlight_indices,
+    },
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use chrono::NaiveDate;
 use crossterm::{
     cursor::{self, Hide, Show},
     event::{read, Event, KeyCode},
@@ -11,341 +24,805 @@ use crossterm::{
     },
 };
 use fuzzy_matcher::{skim::SkimMatcherV2, FuzzyMatcher};
-use inquire::MultiSelect;
+use inquire::{prompt_confirmation, MultiSelect, Text};
 use regex::Regex;
-use std::fs::{self, write};
-use std::io::{stdout, Write};
+use std::{
+    cmp::Reverse,
+    collections::HashSet,
+    io::{stdout, Write},
+};
 use unicode_width::UnicodeWidthStr;
 
-pub fn check(query: String, all: bool) -> AnyResult<()> {
-    let content = fs::read_to_string(get_todo_file_path()?)?;
-    let todo_regex = Regex::new(r"^\s*[-*+]? ?\[ \](.+)$").unwrap();
-    let matcher = SkimMatcherV2::default();
+#[derive(Debug, Clone, Copy)]
+enum Action {
+    Check,
+    Uncheck,
+    Remove,
+    Edit,
+    Open,
+    Move,
+    Start,
+    Cancel,
+    Defer,
+}
 
-    let todos: Vec<(usize, &str, String)> = content
-        .lines()
-        .enumerate()
-        .filter_map(|(i, line)| {
-            todo_regex.captures(line).map(|caps| {
-                let text = caps.get(1).unwrap().as_str().trim().to_string();
-                (i, line, text)
-            })
+impl Action {
+    /// Matches the todos the action applies to, capturing their marker and their text
+    fn regex(self) -> Regex {
+        states().regex_for(|state| match self {
+            Action::Check | Action::Cancel => state.is_open(),
+            Action::Uncheck => !state.is_open(),
+            Action::Start => matches!(state, State::Todo | State::Deferred),
+            Action::Defer => matches!(state, State::Todo | State::Doing),
+            Action::Remove | Action::Edit | Action::Open | Action::Move => true,
         })
-        .collect();
-
-    if todos.is_empty() {
-        println!("No unchecked todos found.");
-        return Ok(());
     }
 
-    let to_check: Vec<usize> = if all {
-        todos.iter().map(|(i, _, _)| *i).collect()
-    } else if query.is_empty() {
-        let options: Vec<_> = todos.iter().map(|(_, _, text)| text.clone()).collect();
-        let choices = MultiSelect::new("Select todo(s) to check:", options).prompt();
-        match choices {
-            Ok(selected) => selected
-                .into_iter()
-                .filter_map(|sel| {
-                    todos
-                        .iter()
-                        .find(|(_, _, text)| *text == sel)
-                        .map(|(i, _, _)| *i)
-                })
-                .collect(),
-            Err(_) => vec![],
+    /// The state the action puts todos in, when it changes their checkbox
+    fn target(self) -> Option<State> {
+        match self {
+            Action::Check => Some(State::Done),
+            Action::Uncheck => Some(State::Todo),
+            Action::Start => Some(State::Doing),
+            Action::Cancel => Some(State::Cancelled),
+            Action::Defer => Some(State::Deferred),
+            Action::Remove | Action::Edit | Action::Open | Action::Move => None,
         }
-    } else {
-        let mut scored: Vec<_> = todos
-            .iter()
-            .filter_map(|(i, _line, text)| {
-                matcher
-                    .fuzzy_match(text, &query)
-                    .map(|score| (score, *i, text))
-            })
-            .collect();
+    }
 
-        if scored.is_empty() {
-            println!("No matching todos.");
-            return Ok(());
+    fn verb(self) -> &'static str {
+        match self {
+            Action::Check => "check",
+            Action::Uncheck => "uncheck",
+            Action::Remove => "remove",
+            Action::Edit => "edit",
+            Action::Open => "open",
+            Action::Move => "move",
+            Action::Start => "start",
+            Action::Cancel => "cancel",
+            Action::Defer => "defer",
         }
+    }
 
-        scored.sort_by(|a, b| b.0.cmp(&a.0));
-        let best_score = scored[0].0;
+    fn past_tense(self) -> &'static str {
+        match self {
+            Action::Check => "Checked",
+            Action::Uncheck => "Unchecked",
+            Action::Remove => "Removed",
+            Action::Edit => "Edited",
+            Action::Open => "Opened",
+            Action::Move => "Moved",
+            Action::Start => "Started",
+            Action::Cancel => "Cancelled",
+            Action::Defer => "Deferred",
+        }
+    }
 
-        let best_matches: Vec<_> = scored
-            .into_iter()
-            .filter(|(score, _, _)| *score == best_score)
-            .collect();
+    /// Why a todo picked by its number is left alone
+    fn skip_reason(self) -> &'static str {
+        match self {
+            Action::Check => "is already checked",
+            Action::Uncheck => "isn't checked",
+            Action::Start => "isn't waiting to be started",
+            Action::Cancel => "is already closed",
+            Action::Defer => "isn't waiting or in progress",
+            Action::Remove | Action::Edit | Action::Open | Action::Move => "isn't a todo",
+        }
+    }
 
-        if best_matches.len() == 1 {
-            vec![best_matches[0].1]
-        } else {
-            let options: Vec<_> = best_matches
-                .iter()
-                .map(|(_, _, line)| line.to_string())
-                .collect();
-
-            let choices =
-                MultiSelect::new("Multiple matches found. Select todo(s) to check:", options)
-                    .prompt();
-
-            match choices {
-                Ok(selected) => selected
-                    .into_iter()
-                    .filter_map(|sel| {
-                        best_matches
-                            .iter()
-                            .find(|(_, _, line)| **line == sel)
-                            .map(|(_, idx, _)| *idx)
-                    })
-                    .collect(),
-                Err(_) => vec![],
-            }
+    fn none_found(self) -> &'static str {
+        match self {
+            Action::Check => "No unchecked todos found.",
+            Action::Uncheck => "No checked todos found.",
+            Action::Start => "No todos waiting to be started found.",
+            Action::Cancel => "No open todos found.",
+            Action::Defer => "No todos waiting or in progress found.",
+            Action::Remove | Action::Edit | Action::Open | Action::Move => "No todos found.",
         }
+    }
+}
+
+pub fn check(args: SelectArgs, all: bool, force: bool) -> AnyResult<()> {
+    apply(Action::Check, args, all, force)
+}
+
+pub fn uncheck(args: SelectArgs, all: bool) -> AnyResult<()> {
+    apply(Action::Uncheck, args, all, false)
+}
+
+pub fn remove(args: SelectArgs, all: bool) -> AnyResult<()> {
+    apply(Action::Remove, args, all, false)
+}
+
+pub fn start(args: SelectArgs, all: bool) -> AnyResult<()> {
+    apply(Action::Start, args, all, false)
+}
+
+pub fn cancel(args: SelectArgs, all: bool) -> AnyResult<()> {
+    apply(Action::Cancel, args, all, false)
+}
+
+pub fn defer(args: SelectArgs, all: bool) -> AnyResult<()> {
+    apply(Action::Defer, args, all, false)
+}
+
+/// Applies `action` to the selected todos. With `force`, todos are checked even when
+/// they are blocked by unchecked ones.
+fn apply(action: Action, args: SelectArgs, all: bool, force: bool) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    let todos = find_todos(&file, action, args.section.as_deref())?;
+
+    if todos.is_empty() {
+        println!("{}", action.none_found());
+        return Ok(());
+    }
+
+    let selected: Vec<usize> = if all {
+        todos.iter().map(|(i, _)| *i).collect()
+    } else {
+        select(&todos, &file, &args, action)?
     };
 
-    if to_check.is_empty() {
+    if selected.is_empty() {
         println!("No todos selected.");
         return Ok(());
     }
 
-    let updated: Vec<String> = content
-        .lines()
-        .enumerate()
-        .map(|(i, line)| {
-            if to_check.contains(&i) {
-                line.replacen("[ ]", "[x]", 1)
-            } else {
-                line.to_string()
+    if let Action::Check = action {
+        check_blockers(&file, &selected, force)?;
+    }
+
+    if args.dry_run {
+        print_dry_run(action.verb(), &file, &selected);
+        return Ok(());
+    }
+
+    match action.target() {
+        Some(state) => {
+            for &i in &selected {
+                if let Some(line) = file.line(i) {
+                    file.set_line(i, states().set(line, state));
+                }
             }
-        })
-        .collect();
+        }
+        None => match action {
+            Action::Remove => file.remove_lines(&selected),
+            _ => unreachable!("not applied to a selection"),
+        },
+    }
 
-    write(get_todo_file_path()?, updated.join("\n"))?;
-    println!("Checked {} todo(s).", to_check.len());
+    file.write(&path)?;
+    println!("{} {} todo(s).", action.past_tense(), selected.len());
     Ok(())
 }
 
-pub fn uncheck(query: String, all: bool) -> AnyResult<()> {
-    let content = fs::read_to_string(get_todo_file_path()?)?;
-    let todo_regex = Regex::new(r"^\s*[-*+]? ?\[x\](.+)$").unwrap();
-    let matcher = SkimMatcherV2::default();
+/// Fails when one of the todos about to be checked is blocked by a todo that stays
+/// unchecked, or only warns with `force`
+fn check_blockers(file: &TodoFile, selected: &[usize], force: bool) -> AnyResult<()> {
+    let checking: HashSet<&str> = selected
+        .iter()
+        .filter_map(|&i| id(file.line(i).unwrap_or_default()))
+        .collect();
+    let open: HashSet<String> = file
+        .open_ids()
+        .into_iter()
+        .filter(|id| !checking.contains(id.as_str()))
+        .collect();
+    let mut blocked = Vec::new();
+    for &i in selected {
+        let line = file.line(i).unwrap_or_default();
+        let waiting: Vec<_> = blockers(line)
+            .into_iter()
+            .filter(|blocker| open.contains(*blocker))
+            .collect();
+        if !waiting.is_empty() {
+            blocked.push(format!(
+                "  {} (waits for {})",
+                line.trim(),
+                waiting.join(", ")
+            ));
+        }
+    }
+    if blocked.is_empty() {
+        return Ok(());
+    }
+    if force {
+        eprintln!("⚠️ Checking blocked todos:\n{}", blocked.join("\n"));
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Some todos are blocked by unchecked ones, check those first or pass --force:\n{}",
+            blocked.join("\n")
+        ))
+    }
+}
 
-    let todos: Vec<(usize, &str, String)> = content
-        .lines()
-        .enumerate()
+/// The line index and text of every todo `action` applies to, in the section titled
+/// `section` when there's one
+fn find_todos(
+    file: &TodoFile,
+    action: Action,
+    section: Option<&str>,
+) -> AnyResult<Vec<(usize, String)>> {
+    let todo_regex = action.regex();
+    let scope = file.scope(section)?;
+    Ok(file
+        .lines_outside_code()
+        .filter(|(i, _)| scope.contains(i))
         .filter_map(|(i, line)| {
             todo_regex.captures(line).map(|caps| {
-                let text = caps.get(1).unwrap().as_str().trim().to_string();
-                (i, line, text)
+                let text = caps.get(2).unwrap().as_str().trim().to_string();
+                (i, text)
             })
         })
-        .collect();
+        .collect())
+}
 
-    if todos.is_empty() {
-        println!("No checked todos found.");
-        return Ok(());
+/// Replaces the text of a todo, keeping its indentation, bullet, checkbox and trailing
+/// attributes like `id:k3f9`. Prompts for the new text, starting from the current one,
+/// when `text` isn't given.
+pub fn edit(query: Option<String>, text: Vec<String>) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    if query.is_none() {
+        ensure_can_prompt("Pass the todo to edit and its new text.")?;
     }
+    let Some(index) = select_one(&file, query, Action::Edit)? else {
+        return Ok(());
+    };
 
-    let to_uncheck: Vec<usize> = if all {
-        todos.iter().map(|(i, _, _)| *i).collect()
-    } else if query.is_empty() {
-        let options: Vec<_> = todos.iter().map(|(_, _, text)| text.clone()).collect();
-        let choices = MultiSelect::new("Select todo(s) to uncheck:", options).prompt();
-        match choices {
-            Ok(selected) => selected
-                .into_iter()
-                .filter_map(|sel| {
-                    todos
-                        .iter()
-                        .find(|(_, _, text)| *text == sel)
-                        .map(|(i, _, _)| *i)
-                })
-                .collect(),
-            Err(_) => vec![],
-        }
+    let line = file.line(index).unwrap_or_default();
+    let prefix = Action::Edit
+        .regex()
+        .captures(line)
+        .and_then(|caps| caps.get(2))
+        .map_or("", |text| {
+            let end = text.start() + usize::from(text.as_str().starts_with(' '));
+            &line[..end]
+        });
+    let content = line[prefix.len()..].trim_end();
+    let attribute = Regex::new(r"^[\w-]+:\S+$").unwrap();
+    let words: Vec<&str> = content.split(' ').collect();
+    let split = words
+        .iter()
+        .rposition(|word| !attribute.is_match(word))
+        .map_or(0, |i| i + 1);
+    let (body, attributes) = words.split_at(split);
+
+    let new_text = if text.is_empty() {
+        ensure_can_prompt("Pass the new text after the query.")?;
+        Text::new("Todo:")
+            .with_initial_value(&body.join(" "))
+            .prompt()
+            .context("Failed to get the new text")?
     } else {
-        let mut scored: Vec<_> = todos
-            .iter()
-            .filter_map(|(i, _line, text)| {
-                matcher
-                    .fuzzy_match(text, &query)
-                    .map(|score| (score, *i, text))
-            })
-            .collect();
+        text.join(" ")
+    };
+    let new_text = new_text.trim();
+    if new_text.is_empty() {
+        return Err(anyhow!(
+            "The new text is empty, use `todo remove` to remove a todo"
+        ));
+    }
 
-        if scored.is_empty() {
-            println!("No matching todos.");
-            return Ok(());
+    let mut new_line = format!("{prefix}{new_text}");
+    for attribute in attributes {
+        if !new_text.split_whitespace().any(|word| word == *attribute) {
+            new_line.push(' ');
+            new_line.push_str(attribute);
         }
+    }
+    if new_line == line {
+        println!("ℹ The todo wasn't changed");
+        return Ok(());
+    }
+    file.set_line(index, new_line);
+    file.write(&path)?;
+    println!("✅ {} todo: {new_text}", Action::Edit.past_tense());
+    Ok(())
+}
 
-        scored.sort_by(|a, b| b.0.cmp(&a.0));
-        let best_score = scored[0].0;
-
-        let best_matches: Vec<_> = scored
-            .into_iter()
-            .filter(|(score, _, _)| *score == best_score)
-            .collect();
+/// Opens the todo file in the editor with the cursor on the line of a todo
+pub fn open(query: Option<String>) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let file = TodoFile::read(&path)?;
+    if query.is_none() {
+        ensure_can_prompt("Pass the todo to open.")?;
+    }
+    match select_one(&file, query, Action::Open)? {
+        Some(index) => open_editor(&path, Some(index + 1)),
+        None => Ok(()),
+    }
+}
 
-        if best_matches.len() == 1 {
-            vec![best_matches[0].1]
-        } else {
-            let options: Vec<_> = best_matches
-                .iter()
-                .map(|(_, _, line)| line.to_string())
-                .collect();
-
-            let choices = MultiSelect::new(
-                "Multiple matches found. Select todo(s) to uncheck:",
-                options,
-            )
-            .prompt();
-
-            match choices {
-                Ok(selected) => selected
-                    .into_iter()
-                    .filter_map(|sel| {
-                        best_matches
-                            .iter()
-                            .find(|(_, _, line)| **line == sel)
-                            .map(|(_, idx, _)| *idx)
-                    })
-                    .collect(),
-                Err(_) => vec![],
+/// Adds an unchecked todo at the end of the file, or of the section titled `section`
+pub fn add(text: Vec<String>, section: Option<String>) -> AnyResult<()> {
+    let text = text.join(" ");
+    let text = text.trim();
+    if text.is_empty() {
+        return Err(anyhow!("The todo is empty"));
+    }
+    let path = get_todo_file_path()?;
+    if !path.exists() {
+        create_todo_file(&path, None)?;
+    }
+    let mut file = TodoFile::read(&path)?;
+    let line = format!("- [ ] {text}");
+    match &section {
+        Some(title) => {
+            if file.insert_into_section(title, vec![line]) {
+                println!("ℹ No section titled `{title}`, added it");
             }
         }
-    };
+        None => file.push_line(line),
+    }
+    file.write(&path)?;
+    println!("✅ Added todo: {text}");
+    Ok(())
+}
 
-    if to_uncheck.is_empty() {
-        println!("No todos selected.");
+/// Moves a todo, with its subtasks, to the end of the section titled `to_section`
+pub fn move_to_section(query: String, to_section: String) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    let Some(index) = select_one(&file, Some(query), Action::Move)? else {
         return Ok(());
-    }
+    };
 
-    let updated: Vec<String> = content
+    let item = file.item(index);
+    let lines: Vec<String> = file
         .lines()
-        .enumerate()
-        .map(|(i, line)| {
-            if to_uncheck.contains(&i) {
-                line.replacen("[x]", "[ ]", 1)
-            } else {
-                line.to_string()
-            }
-        })
+        .skip(item.start)
+        .take(item.len())
+        .map(String::from)
         .collect();
-
-    write(get_todo_file_path()?, updated.join("\n"))?;
-    println!("Unchecked {} todo(s).", to_uncheck.len());
+    // Subtasks moved on their own become top level todos
+    let indent = lines[0].len() - lines[0].trim_start().len();
+    let lines: Vec<String> = lines
+        .iter()
+        .map(|line| line.get(indent..).unwrap_or(line.trim_start()).to_string())
+        .collect();
+    file.remove_lines(&item.collect::<Vec<_>>());
+    if file.insert_into_section(&to_section, lines.clone()) {
+        println!("ℹ No section titled `{to_section}`, added it");
+    }
+    file.write(&path)?;
+    let text = Action::Move
+        .regex()
+        .captures(&lines[0])
+        .map_or(String::new(), |caps| caps[2].trim().to_string());
+    println!(
+        "✅ {} todo to `{}`: {text}",
+        Action::Move.past_tense(),
+        to_section.trim()
+    );
     Ok(())
 }
 
-pub fn remove(query: String, all: bool) -> AnyResult<()> {
-    let content = fs::read_to_string(get_todo_file_path()?)?;
-    let todo_regex = Regex::new(r"^\s*[-*+]? ?\[[ x]\](.+)$").unwrap();
-    let matcher = SkimMatcherV2::default();
-
-    let todos: Vec<(usize, String)> = content
-        .lines()
-        .enumerate()
-        .filter_map(|(i, line)| {
-            todo_regex.captures(line).map(|caps| {
-                let text = caps.get(1).unwrap().as_str().trim().to_string();
-                (i, text)
-            })
-        })
-        .collect();
+/// Moves a todo, with its subtasks, among the todos at the same level
+pub fn move_todo(query: String, position: Position) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    let Some(index) = select_one(&file, Some(query), Action::Move)? else {
+        return Ok(());
+    };
 
-    if todos.is_empty() {
-        println!("No todos found.");
+    let siblings = file.siblings(index);
+    let Some(from) = siblings.iter().position(|item| item.start == index) else {
+        return Err(anyhow!(
+            "Couldn't tell which todos are at the same level as this one"
+        ));
+    };
+    let to = match position {
+        Position::Up => from.saturating_sub(1),
+        Position::Down => (from + 1).min(siblings.len() - 1),
+        Position::Top => 0,
+        Position::Bottom => siblings.len() - 1,
+    };
+    if from == to {
+        println!("ℹ The todo is already there");
         return Ok(());
     }
+    let mut order: Vec<usize> = (0..siblings.len()).collect();
+    let item = order.remove(from);
+    order.insert(to, item);
+    file.reorder(&siblings, &order);
+    file.write(&path)?;
+    let position = match position {
+        Position::Up => "up",
+        Position::Down => "down",
+        Position::Top => "to the top",
+        Position::Bottom => "to the bottom",
+    };
+    println!("✅ {} todo {position}", Action::Move.past_tense());
+    Ok(())
+}
 
-    let to_remove: Vec<usize> = if all {
-        todos.iter().map(|(i, _)| *i).collect()
-    } else if query.is_empty() {
-        let options: Vec<_> = todos.iter().map(|(_, text)| text.clone()).collect();
-        let choices = MultiSelect::new("Select todo(s) to remove:", options).prompt();
-        match choices {
-            Ok(selected) => selected
-                .into_iter()
-                .filter_map(|sel| todos.iter().find(|(_, text)| *text == sel).map(|(i, _)| *i))
-                .collect(),
-            Err(_) => vec![],
+/// Sorts the top level todos between every two headings, in the section titled
+/// `section` when there's one. Subtasks move with their parent, other lines stay where
+/// they are.
+pub fn sort(by: SortKey, section: Option<String>) -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    let scope = file.scope(section.as_deref())?;
+    let todo_regex = states().regex();
+    // Reordering only moves lines before the ones still to look at
+    let todo_lines: HashSet<usize> = file.todo_lines().into_iter().collect();
+
+    let mut sorted = 0;
+    let mut i = scope.start;
+    while i < scope.end {
+        if !todo_lines.contains(&i) {
+            i += 1;
+            continue;
         }
-    } else {
-        let mut scored: Vec<_> = todos
+        let siblings = file.siblings(i);
+        let keys: Vec<(State, Option<NaiveDate>, Option<u8>, String)> = siblings
             .iter()
-            .filter_map(|(i, text)| {
-                matcher
-                    .fuzzy_match(text, &query)
-                    .map(|score| (score, *i, text))
+            .map(|item| {
+                let caps = todo_regex.captures(file.line(item.start).unwrap_or_default());
+                let (state, text) = caps.map_or((State::Todo, String::new()), |caps| {
+                    (
+                        states().state(&caps[1]).unwrap_or(State::Todo),
+                        caps[2].trim().to_string(),
+                    )
+                });
+                (state, due(&text), priority(&text), text.to_lowercase())
             })
             .collect();
+        let mut order: Vec<usize> = (0..siblings.len()).collect();
+        match by {
+            SortKey::State => order.sort_by_key(|&k| keys[k].0),
+            // `None` sorts before `Some`, so todos without one are put last by `is_none`
+            SortKey::Due => order.sort_by_key(|&k| (keys[k].1.is_none(), keys[k].1)),
+            SortKey::Priority => order.sort_by_key(|&k| (keys[k].2.is_none(), keys[k].2)),
+            SortKey::Alpha => order.sort_by(|&a, &b| keys[a].3.cmp(&keys[b].3)),
+        }
+        if order.iter().enumerate().any(|(k, &from)| k != from) {
+            sorted += 1;
+        }
+        i = siblings.last().map_or(i + 1, |item| item.end);
+        file.reorder(&siblings, &order);
+    }
 
-        if scored.is_empty() {
-            println!("No matching todos.");
-            return Ok(());
+    if sorted == 0 {
+        println!("ℹ The todos are already sorted");
+        return Ok(());
+    }
+    file.write(&path)?;
+    println!(
+        "✅ Sorted the todos by {}",
+        format!("{by:?}").to_lowercase()
+    );
+    Ok(())
+}
+
+/// Shows the unchecked todo to work on next, in the current todo file or, with `global`,
+/// in every todo file in the store: the most urgent one that isn't blocked, then the one
+/// due the earliest, then the first one.
+pub fn next(global: bool) -> AnyResult<()> {
+    let files = if global {
+        let mut files = Vec::new();
+        let todo_path = get_todo_path()?;
+        if todo_path.exists() {
+            collect_files(&todo_path, &mut files)?;
         }
+        files.sort();
+        files
+    } else {
+        vec![get_todo_file_path()?]
+    };
 
-        scored.sort_by(|a, b| b.0.cmp(&a.0));
-        let best_score = scored[0].0;
+    let unchecked = states().regex_for(|state| matches!(state, State::Todo | State::Doing));
+    let mut best = None;
+    for path in files {
+        let file = match TodoFile::read(&path) {
+            Ok(file) => file,
+            Err(error) if global => {
+                eprintln!("⚠️ Skipping {}: {error:#}", path.display());
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let open = file.open_ids();
+        for (_, line) in file.lines_outside_code() {
+            let Some(caps) = unchecked.captures(line) else {
+                continue;
+            };
+            let text = caps[2].trim();
+            if blockers(text).iter().any(|blocker| open.contains(*blocker)) {
+                continue;
+            }
+            let (priority, due) = (priority(text), due(text));
+            let key = (priority.is_none(), priority, due.is_none(), due);
+            if best.as_ref().is_none_or(|(best, _, _)| key < *best) {
+                best = Some((key, path.clone(), line.trim().to_string()));
+            }
+        }
+    }
 
-        let best_matches: Vec<_> = scored
-            .into_iter()
-            .filter(|(score, _, _)| *score == best_score)
-            .collect();
+    match best {
+        Some((_, path, line)) => {
+            println!("👉 {line}");
+            if global {
+                match get_original_dir(path.parent().unwrap_or(&path))? {
+                    Some(dir) => println!("   in {}", dir.display()),
+                    None => println!("   in {}", path.display()),
+                }
+            }
+        }
+        None => println!("Nothing left to do 🎉"),
+    }
+    Ok(())
+}
 
-        if best_matches.len() == 1 {
-            vec![best_matches[0].1]
-        } else {
-            let options: Vec<_> = best_matches
+/// Picks the single todo `query` refers to, prompting when that's ambiguous. Returns
+/// the index of its line, or `None` when nothing was picked.
+fn select_one(file: &TodoFile, query: Option<String>, action: Action) -> AnyResult<Option<usize>> {
+    let todos = find_todos(file, action, None)?;
+    if todos.is_empty() {
+        println!("{}", action.none_found());
+        return Ok(None);
+    }
+    let args = SelectArgs {
+        query,
+        ..Default::default()
+    };
+    match select(&todos, file, &args, action)?[..] {
+        [] => {
+            println!("No todos selected.");
+            Ok(None)
+        }
+        [index] => Ok(Some(index)),
+        _ => Err(anyhow!("Pick a single todo to {}", action.verb())),
+    }
+}
+
+/// Picks the todos matching the query in `args`, prompting when that's ambiguous.
+/// Returns their line indices.
+fn select(
+    todos: &[(usize, String)],
+    file: &TodoFile,
+    args: &SelectArgs,
+    action: Action,
+) -> AnyResult<Vec<usize>> {
+    let verb = action.verb();
+    let query = args.query.clone().unwrap_or_default();
+    if query.is_empty() {
+        ensure_can_prompt("Pass a query or use --all.")?;
+        let options: Vec<_> = todos.iter().collect();
+        return prompt_selection(&format!("Select todo(s) to {verb}:"), options);
+    }
+
+    if !args.exact && !args.regex {
+        if let Some(numbers) = parse_numbers(&query, file.todo_lines().len()) {
+            return select_numbers(todos, file, args, &numbers, action)
+                .ok_or_else(|| TodoError::NoMatch { query }.into());
+        }
+        if let Some(id) = query.strip_prefix("id:") {
+            let token = format!("id:{id}");
+            return match todos
                 .iter()
-                .map(|(_, _, line)| line.to_string())
-                .collect();
-
-            let choices =
-                MultiSelect::new("Multiple matches found. Select todo(s) to remove:", options)
-                    .prompt();
-
-            match choices {
-                Ok(selected) => selected
-                    .into_iter()
-                    .filter_map(|sel| {
-                        best_matches
-                            .iter()
-                            .find(|(_, _, line)| **line == sel)
-                            .map(|(_, idx, _)| *idx)
-                    })
-                    .collect(),
-                Err(_) => vec![],
+                .find(|(_, text)| text.split_whitespace().any(|word| word == token))
+            {
+                Some((i, _)) => Ok(vec![*i]),
+                None => Err(TodoError::NoMatch { query }.into()),
+            };
+        }
+    }
+
+    let matches = find_matches(todos, &query, args)?;
+    if matches.is_empty() {
+        return Err(TodoError::NoMatch { query }.into());
+    }
+    if matches.len() == 1 {
+        return Ok(vec![matches[0].0]);
+    }
+    if args.all_matches || args.min_score.is_some() {
+        let confirmed = args.yes || args.dry_run || confirm_selection(verb, &matches)?;
+        return Ok(if confirmed {
+            matches.iter().map(|(i, _)| *i).collect()
+        } else {
+            vec![]
+        });
+    }
+    if !can_prompt() {
+        return Err(ambiguous_match(
+            &query,
+            matches.iter().map(|(_, text)| text),
+        ));
+    }
+    prompt_selection(
+        &format!("Multiple matches found. Select todo(s) to {verb}:"),
+        matches,
+    )
+}
+
+/// Parses todo numbers like `3` or `2-4,7`. Ranges going past `count`, the number of
+/// todos, stop at the first number beyond it, which is enough to reject them.
+fn parse_numbers(query: &str, count: usize) -> Option<Vec<usize>> {
+    let mut numbers = Vec::new();
+    for part in query.split(',') {
+        let part = part.trim();
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse().ok()?;
+                let end: usize = end.trim().parse().ok()?;
+                if start > end {
+                    return None;
+                }
+                numbers.extend(start..=end.min(count.saturating_add(1)).max(start));
             }
+            None => numbers.push(part.parse().ok()?),
         }
+    }
+    Some(numbers)
+}
+
+/// The line indices of the todos with the given numbers, skipping the ones `action`
+/// doesn't apply to or outside of `--section`. Returns `None` when a number is out of
+/// range.
+fn select_numbers(
+    todos: &[(usize, String)],
+    file: &TodoFile,
+    args: &SelectArgs,
+    numbers: &[usize],
+    action: Action,
+) -> Option<Vec<usize>> {
+    let numbered = file.todo_lines();
+    let scope = file.scope(args.section.as_deref()).ok()?;
+    let mut selected = Vec::new();
+    for &number in numbers {
+        let line = *numbered.get(number.checked_sub(1)?)?;
+        if todos.iter().any(|(i, _)| *i == line) {
+            if !selected.contains(&line) {
+                selected.push(line);
+            }
+        } else if !scope.contains(&line) {
+            println!(
+                "ℹ Todo {number} isn't under `{}`",
+                args.section.as_deref().unwrap_or_default()
+            );
+        } else {
+            println!("ℹ Todo {number} {}", action.skip_reason());
+        }
+    }
+    Some(selected)
+}
+
+/// The todos matching `query`, best first. Without `--all-matches` or `--min-score`,
+/// only the fuzzy matches sharing the best score are kept.
+fn find_matches<'a>(
+    todos: &'a [(usize, String)],
+    query: &str,
+    args: &SelectArgs,
+) -> AnyResult<Vec<&'a (usize, String)>> {
+    if args.exact {
+        let query = query.to_lowercase();
+        return Ok(todos
+            .iter()
+            .filter(|(_, text)| text.to_lowercase().contains(&query))
+            .collect());
+    }
+    if args.regex {
+        let regex = Regex::new(query).with_context(|| format!("Invalid regex `{query}`"))?;
+        return Ok(todos
+            .iter()
+            .filter(|(_, text)| regex.is_match(text))
+            .collect());
+    }
+
+    let matcher = SkimMatcherV2::default();
+    let mut scored: Vec<_> = todos
+        .iter()
+        .filter_map(|todo| {
+            matcher
+                .fuzzy_match(&todo.1, query)
+                .map(|score| (score, todo))
+        })
+        .collect();
+    scored.sort_by_key(|b| Reverse(b.0));
+
+    let keep = |score: i64| match args.min_score {
+        Some(min_score) => score >= min_score,
+        None if args.all_matches => true,
+        None => scored.first().is_some_and(|best| best.0 == score),
     };
+    Ok(scored
+        .iter()
+        .filter(|(score, _)| keep(*score))
+        .map(|(_, todo)| *todo)
+        .collect())
+}
 
-    if to_remove.is_empty() {
-        println!("No todos selected.");
-        return Ok(());
+fn prompt_selection(message: &str, options: Vec<&(usize, String)>) -> AnyResult<Vec<usize>> {
+    let texts: Vec<_> = options.iter().map(|(_, text)| text.clone()).collect();
+    match MultiSelect::new(message, texts).raw_prompt() {
+        Ok(selected) => Ok(selected
+            .into_iter()
+            .map(|option| options[option.index].0)
+            .collect()),
+        Err(_) => Ok(vec![]),
     }
+}
 
-    let updated: Vec<_> = content
+fn confirm_selection(verb: &str, matches: &[&(usize, String)]) -> AnyResult<bool> {
+    ensure_can_prompt(&format!("Pass --yes to {verb} all of them."))?;
+    println!("{} todos match:", matches.len());
+    for (_, text) in matches {
+        println!("  {text}");
+    }
+    Ok(prompt_confirmation(format!(
+        "{}{} all of them?",
+        verb[..1].to_uppercase(),
+        &verb[1..]
+    ))?)
+}
+
+fn ambiguous_match<'a>(query: &str, matches: impl Iterator<Item = &'a String>) -> anyhow::Error {
+    TodoError::AmbiguousMatch {
+        query: query.to_string(),
+        matches: matches.cloned().collect(),
+    }
+    .into()
+}
+
+fn print_dry_run(verb: &str, file: &TodoFile, lines: &[usize]) {
+    println!("Would {verb} {} todo(s):", lines.len());
+    for &i in lines {
+        println!("{}", file.line(i).unwrap_or_default().trim_end());
+    }
+}
+
+/// Gives every todo without an ID a short random one, written as `id:<id>` at the end
+/// of the todo, so it can be referred to even after the todos were reordered.
+pub fn assign_ids() -> AnyResult<()> {
+    let path = get_todo_file_path()?;
+    let mut file = TodoFile::read(&path)?;
+    let id_regex = Regex::new(r"(?:^|\s)id:(\w+)").unwrap();
+
+    let mut ids: HashSet<String> = file
         .lines()
-        .enumerate()
-        .filter(|(i, _)| !to_remove.contains(i))
-        .map(|(_, line)| line.to_string())
+        .flat_map(|line| id_regex.captures_iter(line))
+        .map(|caps| caps[1].to_string())
         .collect();
+    let mut assigned = 0;
+    for i in file.todo_lines() {
+        let line = file.line(i).unwrap_or_default();
+        if id_regex.is_match(line) {
+            continue;
+        }
+        let id = loop {
+            let id: String = (0..4).map(|_| fastrand::alphanumeric()).collect();
+            let id = id.to_lowercase();
+            if ids.insert(id.clone()) {
+                break id;
+            }
+        };
+        file.set_line(i, format!("{} id:{id}", line.trim_end()));
+        assigned += 1;
+    }
 
-    write(get_todo_file_path()?, updated.join("\n"))?;
-    println!("Removed {} todo(s).", to_remove.len());
+    if assigned == 0 {
+        println!("ℹ Every todo already has an ID");
+        return Ok(());
+    }
+    file.write(&path)?;
+    println!("✅ Gave {assigned} todo(s) an ID");
     Ok(())
 }
-pub fn search(query: String) -> AnyResult<()> {
-    let content = fs::read_to_string(get_todo_file_path()?)?;
-    let todo_regex = Regex::new(r"^\s*[-*+]? ?\[( |x)\](.+)$").unwrap();
+
+pub fn search(query: String, section: Option<String>) -> AnyResult<()> {
+    let file = TodoFile::read(&get_todo_file_path()?)?;
+    let scope = file.scope(section.as_deref())?;
+    let todo_regex = states().regex();
     let matcher = SkimMatcherV2::default();
 
-    let todos: Vec<(&str, String)> = content
-        .lines()
-        .filter_map(|line| {
+    let todos: Vec<(&str, String)> = file
+        .lines_outside_code()
+        .filter(|(i, _)| scope.contains(i))
+        .filter_map(|(_, line)| {
             todo_regex.captures(line).map(|caps| {
                 let text = caps.get(2).unwrap().as_str().trim().to_string();
                 (line.trim_end(), text)
@@ -359,6 +836,7 @@ pub fn search(query: String) -> AnyResult<()> {
     }
 
     if query.is_empty() {
+        ensure_can_prompt("Pass a query to search for.")?;
         return live_search(todos);
     }
 
@@ -377,8 +855,7 @@ pub fn search(query: String) -> AnyResult<()> {
         .collect();
 
     if scored.is_empty() {
-        println!("No matching todos.");
-        return Ok(());
+        return Err(TodoError::NoMatch { query }.into());
     }
 
     for (_, indices, todo) in scored {
@@ -415,7 +892,7 @@ fn live_search(items: Vec<(&str, String)>) -> AnyResult<()> {
             })
             .collect();
 
-        matches.sort_by(|a, b| b.0.cmp(&a.0));
+        matches.sort_by_key(|b| Reverse(b.0));
 
         execute!(stdout, Clear(ClearType::All), Hide)?;
 
@@ -0,0 +1,148 @@
+use crate::{error::TodoError, relocate::move_tree, utils::get_trash_path};
+use anyhow::{anyhow, Context, Result as AnyResult};
+use chrono::{DateTime, Duration, Local};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+const ENTRY_FILE: &str = "entry.toml";
+
+/// Describes something that was moved to the trash, kept next to it as `entry.toml`
+#[derive(Debug, Serialize, Deserialize)]
+struct TrashEntry {
+    original: PathBuf,
+    deleted_at: DateTime<Local>,
+}
+
+/// Moves a todo file or a directory of todo files to the trash. Returns the id to
+/// restore it with.
+pub fn trash(path: &Path) -> AnyResult<String> {
+    if !path.exists() {
+        return Err(TodoError::NoTodoFile.into());
+    }
+    let trash_path = get_trash_path()?;
+    let deleted_at = Local::now();
+    let timestamp = deleted_at.format("%Y%m%d-%H%M%S").to_string();
+    let mut id = timestamp.clone();
+    let mut n = 1;
+    while trash_path.join(&id).exists() {
+        n += 1;
+        id = format!("{timestamp}-{n}");
+    }
+    let entry_dir = trash_path.join(&id);
+    let name = path.file_name().ok_or_else(|| anyhow!("Invalid path"))?;
+    if let Err(error) = move_tree(path, &entry_dir.join(name)) {
+        // Only removed when nothing was moved yet, a partly moved directory is kept
+        let _ = fs::remove_dir(entry_dir.join(name));
+        let _ = fs::remove_dir(&entry_dir);
+        return Err(error.context(format!("Failed to move {} to the trash", path.display())));
+    }
+    let entry = TrashEntry {
+        original: path.to_path_buf(),
+        deleted_at,
+    };
+    fs::write(entry_dir.join(ENTRY_FILE), toml::to_string(&entry)?)
+        .context("Failed to write trash entry")?;
+    Ok(id)
+}
+
+pub fn list() -> AnyResult<()> {
+    let entries = load_entries()?;
+    if entries.is_empty() {
+        println!("The trash is empty.");
+        return Ok(());
+    }
+    for (id, entry) in entries {
+        println!(
+            "{id}  {}  {}",
+            entry.deleted_at.format("%Y-%m-%d %H:%M"),
+            entry.original.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn restore(id: &str) -> AnyResult<()> {
+    let entry_dir = get_trash_path()?.join(id);
+    let entry = load_entry(&entry_dir)?
+        .ok_or_else(|| anyhow!("No trash entry with id `{id}`, see `todo trash list`"))?;
+    let name = entry
+        .original
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid path"))?;
+    let (restored, merged) = move_tree(&entry_dir.join(name), &entry.original)?;
+    fs::remove_dir_all(&entry_dir).context("Failed to clean up the trash")?;
+    println!(
+        "✅ Restored {restored} todo file(s) to {}",
+        entry.original.display()
+    );
+    if merged > 0 {
+        println!("ℹ Merged {merged} of them into existing todo files");
+    }
+    Ok(())
+}
+
+pub fn empty(older_than: Option<String>) -> AnyResult<()> {
+    let cutoff = match older_than {
+        Some(age) => Some(Local::now() - parse_age(&age)?),
+        None => None,
+    };
+    let trash_path = get_trash_path()?;
+    let mut removed = 0;
+    for (id, entry) in load_entries()? {
+        if cutoff.is_none_or(|cutoff| entry.deleted_at < cutoff) {
+            fs::remove_dir_all(trash_path.join(&id))
+                .with_context(|| format!("Failed to remove {id} from the trash"))?;
+            removed += 1;
+        }
+    }
+    println!("✅ Removed {removed} item(s) from the trash.");
+    Ok(())
+}
+
+/// Parses ages like `30d`, `2w` or `12h`.
+fn parse_age(age: &str) -> AnyResult<Duration> {
+    let invalid = || anyhow!("Invalid age `{age}`, use e.g. 30m, 12h, 7d or 2w");
+    let unit_start = age
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let (amount, unit) = age.split_at(unit_start);
+    let amount: i64 = amount.parse().map_err(|_| invalid())?;
+    match unit {
+        "m" => Ok(Duration::minutes(amount)),
+        "h" => Ok(Duration::hours(amount)),
+        "d" => Ok(Duration::days(amount)),
+        "w" => Ok(Duration::weeks(amount)),
+        _ => Err(invalid()),
+    }
+}
+
+/// The entries in the trash with their ids, oldest first.
+fn load_entries() -> AnyResult<Vec<(String, TrashEntry)>> {
+    let trash_path = get_trash_path()?;
+    if !trash_path.exists() {
+        return Ok(Vec::new());
+    }
+    let mut entries = Vec::new();
+    for dir in fs::read_dir(trash_path)? {
+        let dir = dir?.path();
+        if let Some(entry) = load_entry(&dir)? {
+            let id = dir.file_name().unwrap().to_string_lossy().to_string();
+            entries.push((id, entry));
+        }
+    }
+    entries.sort_by_key(|(_, entry)| entry.deleted_at);
+    Ok(entries)
+}
+
+fn load_entry(entry_dir: &Path) -> AnyResult<Option<TrashEntry>> {
+    match fs::read_to_string(entry_dir.join(ENTRY_FILE)) {
+        Ok(content) => Ok(Some(toml::from_str(&content).map_err(|e| {
+            anyhow!("Invalid trash entry {}: {e}", entry_dir.display())
+        })?)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).context("Failed to read trash entry"),
+    }
+}
@@ -1,11 +1,49 @@
-use crate::config::{load_config, Config};
-use anyhow::{anyhow, Result as AnyResult};
+use crate::{
+    config::{load_config, Config, Identity, LocalConfig},
+    error::TodoError,
+    git::{find_origin, normalize_remote},
+};
+use anyhow::{anyhow, Context, Result as AnyResult};
 use crossterm::style::{Color, Stylize};
-use std::{collections::HashSet, env, fs, path::PathBuf};
+use std::{
+    collections::HashSet,
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Component, Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static NO_INPUT: AtomicBool = AtomicBool::new(false);
+
+/// Marks a directory whose todo file lives inside it, see `todo init --local`
+pub const LOCAL_MARKER: &str = ".todo.toml";
+
+/// Never prompt, as if stdin wasn't a terminal. Set by `--no-input`.
+pub fn disable_input() {
+    NO_INPUT.store(true, Ordering::Relaxed);
+}
+
+/// Whether the user can be prompted, i.e. input wasn't disabled and both stdin and stdout
+/// are terminals.
+pub fn can_prompt() -> bool {
+    !NO_INPUT.load(Ordering::Relaxed) && io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Fails when the user can't be prompted, with `hint` telling them how to avoid the prompt.
+pub fn ensure_can_prompt(hint: &str) -> AnyResult<()> {
+    if can_prompt() {
+        Ok(())
+    } else {
+        Err(TodoError::InputRequired {
+            hint: hint.to_string(),
+        }
+        .into())
+    }
+}
 
 pub fn get_home_dir() -> AnyResult<PathBuf> {
     env::home_dir().ok_or_else(|| {
-        anyhow!("❌ Could not determine your home directory. Please check your environment.")
+        anyhow!("Could not determine your home directory. Please check your environment.")
     })
 }
 
@@ -17,31 +55,247 @@ pub fn get_config_path() -> AnyResult<PathBuf> {
     Ok(get_home_dir()?.join(".todo").join("config.toml"))
 }
 
+pub fn get_trash_path() -> AnyResult<PathBuf> {
+    Ok(get_home_dir()?.join(".todo").join("trash"))
+}
+
+pub fn get_templates_path() -> AnyResult<PathBuf> {
+    Ok(get_home_dir()?.join(".todo").join("templates"))
+}
+
+pub fn get_checklists_path() -> AnyResult<PathBuf> {
+    Ok(get_home_dir()?.join(".todo").join("checklists"))
+}
+
+pub fn get_syncs_path() -> AnyResult<PathBuf> {
+    Ok(get_home_dir()?.join(".todo").join("syncs.toml"))
+}
+
+pub fn get_sync_bases_path() -> AnyResult<PathBuf> {
+    Ok(get_home_dir()?.join(".todo").join("bases"))
+}
+
 pub fn get_cwd_todo_dir() -> AnyResult<PathBuf> {
+    get_todo_dir(&env::current_dir()?)
+}
+
+/// Directories at the top of the store that don't belong to the home directory: todos
+/// identified by their git remote, kept for a root from the config, outside of any root,
+/// and of home directories that would clash with one of these
+const RESERVED: [&str; 4] = ["_remote", "_alias", "_root", "_home"];
+
+/// The directory in the store holding the todos of `dir`
+pub fn get_todo_dir(dir: &Path) -> AnyResult<PathBuf> {
     let home_dir = get_home_dir()?;
     let todo_path = get_todo_path()?;
-    let cwd = env::current_dir()?;
-    let stripped = cwd.strip_prefix(&home_dir).unwrap_or(&cwd);
-    Ok(todo_path.join(stripped))
+    let config = load_config()?.unwrap_or_default();
+    if config.identity == Identity::Remote {
+        if let Some((root, url)) = find_origin(dir) {
+            if let Some(remote) = normalize_remote(&url) {
+                let relative = dir.strip_prefix(&root).unwrap_or(Path::new(""));
+                return Ok(todo_path.join("_remote").join(remote).join(relative));
+            }
+        }
+    }
+    // The same directory can be reached through symlinks
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let home_dir = home_dir.canonicalize().unwrap_or(home_dir);
+    let roots = config
+        .roots
+        .iter()
+        .map(|(name, root)| {
+            (
+                todo_path.join("_alias").join(name),
+                root.canonicalize().unwrap_or(root.clone()),
+            )
+        })
+        .chain([(todo_path.clone(), home_dir)]);
+    let closest_root = roots
+        .filter(|(_, root)| dir.starts_with(root))
+        .max_by_key(|(_, root)| root.components().count());
+    match closest_root {
+        Some((store_root, root)) => {
+            let relative = dir.strip_prefix(&root)?;
+            let reserved = relative
+                .components()
+                .next()
+                .is_some_and(|first| RESERVED.iter().any(|name| first.as_os_str() == *name));
+            if store_root == todo_path && reserved {
+                Ok(todo_path.join("_home").join(relative))
+            } else {
+                Ok(store_root.join(relative))
+            }
+        }
+        // Keep directories outside of any root apart from the ones in the home directory
+        None => {
+            Ok(dir
+                .components()
+                .fold(todo_path.join("_root"), |path, component| match component {
+                    Component::Prefix(prefix) => path.join(
+                        prefix
+                            .as_os_str()
+                            .to_string_lossy()
+                            .replace([':', '\\'], ""),
+                    ),
+                    Component::Normal(part) => path.join(part),
+                    _ => path,
+                }))
+        }
+    }
+}
+
+/// The directory whose todos are kept in `store_dir`, the reverse of [`get_todo_dir`].
+/// Returns `None` for todos identified by a git remote, or kept for a root that is no
+/// longer in the config.
+pub fn get_original_dir(store_dir: &Path) -> AnyResult<Option<PathBuf>> {
+    let todo_path = get_todo_path()?;
+    let relative = store_dir.strip_prefix(&todo_path)?;
+    if relative.starts_with("_remote") {
+        return Ok(None);
+    }
+    if let Ok(rest) = relative.strip_prefix("_root") {
+        return Ok(Some(Path::new(&Component::RootDir).join(rest)));
+    }
+    if let Ok(rest) = relative.strip_prefix("_alias") {
+        let config = load_config()?.unwrap_or_default();
+        for (name, root) in &config.roots {
+            if let Ok(rest) = rest.strip_prefix(name) {
+                return Ok(Some(root.join(rest)));
+            }
+        }
+        // The root was removed from the config
+        return Ok(None);
+    }
+    let relative = relative.strip_prefix("_home").unwrap_or(relative);
+    Ok(Some(get_home_dir()?.join(relative)))
+}
+
+/// Makes `path` absolute relative to the current directory and resolves `.` and `..`
+/// without touching the filesystem, so it also works for paths that don't exist.
+pub fn absolute_path(path: &Path) -> AnyResult<PathBuf> {
+    let mut absolute = PathBuf::new();
+    for component in env::current_dir()?.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute.pop();
+            }
+            component => absolute.push(component),
+        }
+    }
+    Ok(absolute)
 }
 
 pub fn get_todo_file_path() -> AnyResult<PathBuf> {
+    match find_local_project()? {
+        Some((dir, local)) => Ok(dir.join(local.path)),
+        None => get_store_todo_file_path(),
+    }
+}
+
+/// The todo file for the current directory in `~/.todo/todos`, ignoring local projects.
+/// A todo file an older version kept elsewhere in the store is moved there first.
+pub fn get_store_todo_file_path() -> AnyResult<PathBuf> {
     let config = load_config()?.unwrap_or_default();
-    Ok(get_cwd_todo_dir()?.join(config.filename + &config.extension))
+    let filename = config.filename + &config.extension;
+    let path = get_cwd_todo_dir()?.join(&filename);
+    if path.exists() {
+        return Ok(path);
+    }
+    let todo_path = get_todo_path()?;
+    let legacy = get_legacy_todo_dirs()?
+        .into_iter()
+        .map(|dir| dir.join(&filename))
+        .find(|legacy| legacy.starts_with(&todo_path) && legacy.is_file());
+    if let Some(legacy) = legacy {
+        fs::create_dir_all(path.parent().unwrap()).context("Failed to create todo directory")?;
+        fs::rename(&legacy, &path)
+            .with_context(|| format!("Failed to move {}", legacy.display()))?;
+        // Left in place when other todos are kept under it
+        let _ = fs::remove_dir(legacy.parent().unwrap());
+        eprintln!(
+            "ℹ Moved the todos of this directory from {} to {}",
+            legacy.display(),
+            path.display()
+        );
+    }
+    Ok(path)
+}
+
+/// Where versions before symlinks were resolved and directories outside the home
+/// directory were namespaced kept the todos of the current directory: in the store
+/// relative to the home directory, or in the directory itself when it's outside of it.
+/// Only the ones differing from the current location are returned.
+pub fn get_legacy_todo_dirs() -> AnyResult<Vec<PathBuf>> {
+    let home_dir = get_home_dir()?;
+    let todo_path = get_todo_path()?;
+    let cwd = env::current_dir()?;
+    // The shell's path to the current directory keeps the symlinks it went through
+    let pwd = env::var_os("PWD")
+        .map(PathBuf::from)
+        .filter(|pwd| pwd.is_absolute() && pwd.canonicalize().ok() == cwd.canonicalize().ok());
+    let current = get_cwd_todo_dir()?;
+    let mut dirs = Vec::new();
+    for dir in [Some(cwd), pwd].into_iter().flatten() {
+        let legacy = todo_path.join(dir.strip_prefix(&home_dir).unwrap_or(&dir));
+        // These now belong to other directories
+        let reserved = legacy.strip_prefix(&todo_path).is_ok_and(|relative| {
+            relative
+                .components()
+                .next()
+                .is_some_and(|first| RESERVED.iter().any(|name| first.as_os_str() == *name))
+        });
+        if legacy != current && !reserved && !dirs.contains(&legacy) {
+            dirs.push(legacy);
+        }
+    }
+    Ok(dirs)
+}
+
+/// Finds the closest directory, starting from the current one, that was marked by
+/// `todo init --local`
+pub fn find_local_project() -> AnyResult<Option<(PathBuf, LocalConfig)>> {
+    let cwd = env::current_dir()?;
+    for dir in cwd.ancestors() {
+        let marker = dir.join(LOCAL_MARKER);
+        match fs::read_to_string(&marker) {
+            Ok(content) => {
+                let local = toml::from_str(&content).map_err(|e| TodoError::ConfigInvalid {
+                    path: marker.clone(),
+                    reason: e.message().to_string(),
+                })?;
+                return Ok(Some((dir.to_path_buf(), local)));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| format!("Failed to read {}", marker.display()))
+            }
+        }
+    }
+    Ok(None)
 }
 
 pub fn resolve_editor(editor: String) -> AnyResult<String> {
     if let Some(var) = editor.strip_prefix("$") {
-        env::var(var).map_err(|_| {
-            anyhow!(
-                "❌ Environment variable `{var}` is not set. Please set it or update your config."
-            )
-        })
+        Ok(env::var(var).map_err(|_| TodoError::EditorMissing { editor })?)
     } else {
         Ok(editor)
     }
 }
 
+/// Collects every file under `dir`, recursively.
+pub fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> AnyResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
 pub fn update_todos(dir: PathBuf, new_config: &Config) -> AnyResult<()> {
     let new_filename = new_config.filename.clone() + &new_config.extension;
     for entry in fs::read_dir(dir)? {
@@ -53,7 +307,7 @@ pub fn update_todos(dir: PathBuf, new_config: &Config) -> AnyResult<()> {
             fs::rename(
                 &path,
                 path.parent()
-                    .ok_or_else(|| anyhow!("❌ Invalid path"))?
+                    .ok_or_else(|| anyhow!("Invalid path"))?
                     .join(&new_filename),
             )?;
         }